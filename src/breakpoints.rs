@@ -0,0 +1,276 @@
+//! Breakpoints, watchpoints and conditions, which stop [`Emulator::run`] without editing the program.
+//!
+//! ```rust
+//! # use lasagna::breakpoints::{Register, StopReason, WatchKind};
+//! # use lasagna::emulator::Emulator;
+//! # use lasagna::parser::assemble;
+//! let program = assemble("
+//!     literal 0x1234_u16
+//!     read u16
+//!     copy
+//!     add u16
+//!     interrupt
+//! ").unwrap();
+//! let mut emulator = Emulator::default();
+//! emulator.memory[0x20000 .. 0x20000 + program.len()].copy_from_slice(&program);
+//!
+//! emulator.add_breakpoint(0x20008);
+//! let watchpoint = emulator.add_watchpoint(0 .. 2, WatchKind::Read);
+//! let condition = emulator.add_condition(Register::Val1, 0x24680000);
+//!
+//! assert!(matches!(emulator.run(), StopReason::Watchpoint {id, ..} if id == watchpoint));
+//! assert_eq!(emulator.run(), StopReason::Breakpoint(0x20008));
+//! assert!(matches!(emulator.run(), StopReason::Condition {id, ..} if id == condition));
+//! assert!(matches!(emulator.run(), StopReason::Interrupt(_)));
+//! ```
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::Range;
+
+use crate::emulator::{Access, Emulator, Interrupt};
+use crate::trace::MemoryAccess;
+
+/// A register of an emulator, for conditions to check.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Register {
+    Val1,
+    Val2,
+    Ptr,
+    Cur,
+    Stat
+}
+
+impl Register {
+    /// Every register, in order.
+    pub const ALL: [Self; 5] = [Self::Val1, Self::Val2, Self::Ptr, Self::Cur, Self::Stat];
+
+    /// Gets a register from its name, ignoring case.
+    ///
+    /// ```rust
+    /// # use lasagna::breakpoints::Register;
+    /// assert_eq!(Register::from_name("val1"), Some(Register::Val1));
+    /// assert_eq!(Register::from_name("PC"), None);
+    /// ```
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|register| register.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The name of the register, like `VAL1`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Val1 => "VAL1",
+            Self::Val2 => "VAL2",
+            Self::Ptr => "PTR",
+            Self::Cur => "CUR",
+            Self::Stat => "STAT"
+        }
+    }
+
+    /// Gets the value of this register, with `VAL1` and `VAL2` as big-endian u32s.
+    pub fn get(&self, emulator: &Emulator) -> u32 {
+        match self {
+            Self::Val1 => u32::from_be_bytes(emulator.val1),
+            Self::Val2 => u32::from_be_bytes(emulator.val2),
+            Self::Ptr => emulator.ptr,
+            Self::Cur => emulator.cur,
+            Self::Stat => emulator.stat
+        }
+    }
+
+    /// Sets the value of this register, with `VAL1` and `VAL2` as big-endian u32s.
+    pub fn set(&self, emulator: &mut Emulator, value: u32) {
+        match self {
+            Self::Val1 => emulator.val1 = value.to_be_bytes(),
+            Self::Val2 => emulator.val2 = value.to_be_bytes(),
+            Self::Ptr => emulator.ptr = value,
+            Self::Cur => emulator.cur = value,
+            Self::Stat => emulator.stat = value
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The kinds of memory access that a watchpoint stops on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// Either reading or writing.
+    Access
+}
+
+impl WatchKind {
+    fn matches(&self, access: Access) -> bool {
+        matches!((self, access), (Self::Read, Access::Read) | (Self::Write, Access::Write) | (Self::Access, _))
+    }
+}
+
+/// Why [`Emulator::run`] stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    /// `CUR` reached a breakpoint at the given address, before running the instruction there.
+    Breakpoint(u32),
+    /// A watched range of memory was accessed by the last step.
+    Watchpoint {id: usize, access: MemoryAccess},
+    /// A register became equal to the value of a condition after the last step.
+    Condition {id: usize, register: Register, value: u32},
+    /// The emulator was interrupted.
+    Interrupt(Interrupt)
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Breakpoint(addr) => write!(f, "breakpoint at {addr:08X}"),
+            Self::Watchpoint {id, access} => {
+                let kind = if access.access == Access::Write { "write" } else { "read" };
+                write!(f, "watchpoint {id}: {kind} of {} bytes at {:08X}", access.data.len(), access.addr)
+            },
+            Self::Condition {id, register, value} => write!(f, "condition {id}: {register} = {value:08X}"),
+            Self::Interrupt(interrupt) => write!(f, "{interrupt}")
+        }
+    }
+}
+
+/// The breakpoints, watchpoints and conditions of an emulator.
+#[derive(Debug, Clone, Default)]
+pub struct Breakpoints {
+    addresses: BTreeSet<u32>,
+    watchpoints: Vec<(usize, Range<u32>, WatchKind)>,
+    conditions: Vec<(usize, Register, u32)>,
+    next_id: usize,
+    /// The breakpoint that was last stopped at, if nothing has run since.
+    stopped_at: Option<u32>
+}
+
+impl Breakpoints {
+    /// The addresses of every breakpoint, in order.
+    pub fn addresses(&self) -> impl Iterator<Item = u32> + '_ {
+        self.addresses.iter().copied()
+    }
+
+    /// Every watchpoint, with its ID.
+    pub fn watchpoints(&self) -> impl Iterator<Item = (usize, Range<u32>, WatchKind)> + '_ {
+        self.watchpoints.iter().cloned()
+    }
+
+    /// Every condition, with its ID.
+    pub fn conditions(&self) -> impl Iterator<Item = (usize, Register, u32)> + '_ {
+        self.conditions.iter().copied()
+    }
+
+    fn next_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+impl Emulator {
+    /// Adds a breakpoint, stopping before running the instruction at `addr`.
+    /// Returns `false` if there already was one.
+    pub fn add_breakpoint(&mut self, addr: u32) -> bool {
+        self.breakpoints.addresses.insert(addr)
+    }
+
+    /// Removes the breakpoint at `addr`, returning `false` if there wasn't one.
+    pub fn remove_breakpoint(&mut self, addr: u32) -> bool {
+        self.breakpoints.addresses.remove(&addr)
+    }
+
+    /// Adds a watchpoint, stopping after any step that accesses memory in `range` in the given way.
+    /// This covers accesses at `PTR` and on the stack, but not accesses made by the host.
+    /// Returns the watchpoint's ID.
+    pub fn add_watchpoint(&mut self, range: Range<u32>, kind: WatchKind) -> usize {
+        let id = self.breakpoints.next_id();
+        self.breakpoints.watchpoints.push((id, range, kind));
+        id
+    }
+
+    /// Removes the watchpoint with the given ID, returning `false` if there wasn't one.
+    pub fn remove_watchpoint(&mut self, id: usize) -> bool {
+        let length = self.breakpoints.watchpoints.len();
+        self.breakpoints.watchpoints.retain(|(other, ..)| *other != id);
+        self.breakpoints.watchpoints.len() != length
+    }
+
+    /// Adds a condition, stopping after any step that makes the register equal to `value`.
+    /// Returns the condition's ID.
+    pub fn add_condition(&mut self, register: Register, value: u32) -> usize {
+        let id = self.breakpoints.next_id();
+        self.breakpoints.conditions.push((id, register, value));
+        id
+    }
+
+    /// Removes the condition with the given ID, returning `false` if there wasn't one.
+    pub fn remove_condition(&mut self, id: usize) -> bool {
+        let length = self.breakpoints.conditions.len();
+        self.breakpoints.conditions.retain(|(other, ..)| *other != id);
+        self.breakpoints.conditions.len() != length
+    }
+
+    /// Steps until a breakpoint, watchpoint or condition is hit, or until an interrupt.
+    ///
+    /// Running again after stopping at a breakpoint carries on past it.
+    pub fn run(&mut self) -> StopReason {
+        loop {
+            if let Some(reason) = self.run_for(u64::MAX) {
+                return reason;
+            }
+        }
+    }
+
+    /// Runs like [`Emulator::run`], but for at most `steps` steps,
+    /// returning `None` if nothing stopped it before then.
+    pub fn run_for(&mut self, steps: u64) -> Option<StopReason> {
+        for _ in 0..steps {
+            let resuming = self.breakpoints.stopped_at.take() == Some(self.cur);
+            if !resuming && self.breakpoints.addresses.contains(&self.cur) {
+                self.breakpoints.stopped_at = Some(self.cur);
+                return Some(StopReason::Breakpoint(self.cur));
+            }
+            if let Some(reason) = self.step_watched() {
+                return Some(reason);
+            }
+        }
+        None
+    }
+
+    /// Steps once, returning why to stop if a watchpoint or condition was hit, or if there was an interrupt.
+    /// This ignores breakpoints.
+    pub fn step_watched(&mut self) -> Option<StopReason> {
+        let watching = !self.breakpoints.watchpoints.is_empty();
+        if watching {
+            self.accesses = Some(Vec::new());
+        }
+        let before: Vec<u32> = self.breakpoints.conditions.iter()
+            .map(|(_, register, _)| register.get(self))
+            .collect();
+        let result = self.step();
+        let accesses = if watching { self.accesses.take().unwrap_or_default() } else { Vec::new() };
+        if let Some(interrupt) = result {
+            return Some(StopReason::Interrupt(interrupt));
+        }
+
+        for access in accesses {
+            let start = access.addr as u64;
+            let end = start + access.data.len() as u64;
+            let hit = self.breakpoints.watchpoints.iter().find(|(_, range, kind)| {
+                kind.matches(access.access) && start < range.end as u64 && (range.start as u64) < end
+            });
+            if let Some((id, ..)) = hit {
+                return Some(StopReason::Watchpoint {id: *id, access});
+            }
+        }
+        for (&(id, register, value), before) in self.breakpoints.conditions.iter().zip(before) {
+            if before != value && register.get(self) == value {
+                return Some(StopReason::Condition {id, register, value});
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,253 @@
+//! Memory-mapped devices, which take over reads and writes to ranges of addresses.
+//!
+//! Any address without a device mapped to it falls back to plain RAM.
+//!
+//! ```rust
+//! # use lasagna::bus::Device;
+//! # use lasagna::emulator::Emulator;
+//! # use lasagna::parser::assemble;
+//! /// Counts up every time it's read, and resets when written to.
+//! struct Counter(u8);
+//!
+//! impl Device for Counter {
+//!     fn read(&mut self, _addr: u32, buf: &mut [u8]) -> Option<u32> {
+//!         self.0 += 1;
+//!         buf.fill(self.0);
+//!         None
+//!     }
+//!
+//!     fn write(&mut self, _addr: u32, data: &[u8]) -> Option<u32> {
+//!         self.0 = data[0];
+//!         None
+//!     }
+//! }
+//!
+//! let mut emulator = Emulator::default();
+//! emulator.bus.map(0x8000 .. 0x8001, Counter(0)).unwrap();
+//!
+//! let program = assemble("literal 0x8000_u32\nread u32\nmove\nread u8\nread u8").unwrap();
+//! emulator.memory[0x20000 .. 0x20000 + program.len()].copy_from_slice(&program);
+//! for _ in 0..5 {
+//!     assert_eq!(emulator.step(), None);
+//! }
+//! assert_eq!(emulator.val1[0], 2);
+//! ```
+use std::cell::RefCell;
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// A device that can be mapped onto a range of memory with [`Bus::map`].
+///
+/// Addresses passed to a device are relative to the start of its range,
+/// and accesses never extend past the end of it.
+/// Either method can return an interrupt code to raise.
+pub trait Device {
+    /// Reads `buf.len()` bytes starting at `addr` into `buf`.
+    fn read(&mut self, addr: u32, buf: &mut [u8]) -> Option<u32>;
+
+    /// Writes `data` starting at `addr`.
+    fn write(&mut self, addr: u32, data: &[u8]) -> Option<u32>;
+}
+
+/// An error raised when a device can't be mapped onto a [`Bus`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapError {
+    /// The range was empty.
+    Empty,
+    /// The range overlaps with the range of an already mapped device, which is given.
+    Overlaps(Range<u32>)
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => f.write_str("can't map a device onto an empty range"),
+            Self::Overlaps(range) =>
+                write!(f, "range overlaps with a device at {:#010X}..{:#010X}", range.start, range.end)
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+/// Why an access through the bus failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Fault {
+    /// RAM was accessed out of bounds at the given address.
+    OutOfBounds(u32),
+    /// A device raised an interrupt with the given code.
+    Raised(u32)
+}
+
+#[derive(Clone)]
+struct Mapping {
+    range: Range<u32>,
+    device: Rc<RefCell<dyn Device>>
+}
+
+/// A section of a single access, either going to a device or to RAM.
+struct Segment {
+    /// The absolute address the section starts at.
+    addr: u32,
+    /// Where the section is within the access.
+    offset: Range<usize>,
+    /// The index of the mapping that handles this section, or `None` for RAM.
+    mapping: Option<usize>
+}
+
+/// The devices mapped onto an emulator's memory.
+///
+/// Devices are shared between clones of a bus, like [`crate::emulator::Callback`]s.
+#[derive(Clone, Default)]
+pub struct Bus {
+    /// Sorted by the start of their range, with no overlaps.
+    mappings: Vec<Mapping>
+}
+
+impl Bus {
+    /// Creates a bus with no devices mapped.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maps a device onto a range of addresses.
+    ///
+    /// # Errors
+    /// * The range is empty.
+    /// * The range overlaps with a device that's already mapped.
+    ///
+    /// ```rust
+    /// # use lasagna::bus::{Bus, Device, MapError};
+    /// # struct Null;
+    /// # impl Device for Null {
+    /// #     fn read(&mut self, _: u32, _: &mut [u8]) -> Option<u32> { None }
+    /// #     fn write(&mut self, _: u32, _: &[u8]) -> Option<u32> { None }
+    /// # }
+    /// let mut bus = Bus::new();
+    /// assert!(bus.map(0x100 .. 0x200, Null).is_ok());
+    /// assert_eq!(bus.map(0x1FF .. 0x300, Null), Err(MapError::Overlaps(0x100 .. 0x200)));
+    /// assert_eq!(bus.map(0x300 .. 0x300, Null), Err(MapError::Empty));
+    /// assert!(bus.is_mapped(0x1FF) && !bus.is_mapped(0x200));
+    /// ```
+    pub fn map(&mut self, range: Range<u32>, device: impl Device + 'static) -> Result<(), MapError> {
+        if range.is_empty() {
+            return Err(MapError::Empty);
+        }
+        let index = self.mappings.partition_point(|mapping| mapping.range.start < range.start);
+        let neighbours = self.mappings[index.saturating_sub(1) ..].iter().take(2);
+        for mapping in neighbours {
+            if mapping.range.start < range.end && range.start < mapping.range.end {
+                return Err(MapError::Overlaps(mapping.range.clone()));
+            }
+        }
+        self.mappings.insert(index, Mapping {range, device: Rc::new(RefCell::new(device))});
+        Ok(())
+    }
+
+    /// Unmaps the device whose range contains `addr`, returning its range.
+    pub fn unmap(&mut self, addr: u32) -> Option<Range<u32>> {
+        let index = self.find(addr)?;
+        Some(self.mappings.remove(index).range)
+    }
+
+    /// Checks if there's a device mapped at `addr`.
+    pub fn is_mapped(&self, addr: u32) -> bool {
+        self.find(addr).is_some()
+    }
+
+    /// Finds the index of the mapping containing `addr`.
+    fn find(&self, addr: u32) -> Option<usize> {
+        let index = self.mappings.partition_point(|mapping| mapping.range.end <= addr);
+        self.mappings.get(index)
+            .filter(|mapping| mapping.range.contains(&addr))
+            .map(|_| index)
+    }
+
+    /// Splits an access of `len` bytes at `addr` into the sections handled by each device and RAM,
+    /// checking that the sections going to RAM are in bounds.
+    fn segments(&self, ram: usize, addr: u32, len: usize) -> Result<Vec<Segment>, Fault> {
+        let end = addr as u64 + len as u64;
+        if end > 1 << 32 {
+            return Err(Fault::OutOfBounds(addr));
+        }
+        let mut segments = Vec::new();
+        let mut start = addr as u64;
+        while start < end {
+            let index = self.mappings.partition_point(|mapping| (mapping.range.end as u64) <= start);
+            let (until, mapping) = match self.mappings.get(index) {
+                Some(mapping) if mapping.range.start as u64 <= start =>
+                    (mapping.range.end as u64, Some(index)),
+                Some(mapping) => (mapping.range.start as u64, None),
+                None => (end, None)
+            };
+            let until = until.min(end);
+            if mapping.is_none() && until > ram as u64 {
+                return Err(Fault::OutOfBounds(start.max(ram as u64) as u32));
+            }
+            segments.push(Segment {
+                addr: start as u32,
+                offset: (start - addr as u64) as usize .. (until - addr as u64) as usize,
+                mapping
+            });
+            start = until;
+        }
+        Ok(segments)
+    }
+
+    /// Reads `buf.len()` bytes at `addr`, from devices or from `memory`.
+    ///
+    /// Sections coming from `memory` are passed to `ram` along with their slice of memory,
+    /// which is expected to read them.
+    pub(crate) fn read(
+        &self, memory: &[u8], addr: u32, buf: &mut [u8],
+        mut ram: impl FnMut(&[u8], u32, &mut [u8]) -> Option<u32>
+    ) -> Result<(), Fault> {
+        for segment in self.segments(memory.len(), addr, buf.len())? {
+            let buf = &mut buf[segment.offset.clone()];
+            let code = match segment.mapping {
+                Some(index) => {
+                    let mapping = &self.mappings[index];
+                    let relative = segment.addr - mapping.range.start;
+                    mapping.device.borrow_mut().read(relative, buf)
+                },
+                None => {
+                    let start = segment.addr as usize;
+                    ram(&memory[start .. start + buf.len()], segment.addr, buf)
+                }
+            };
+            if let Some(code) = code {
+                return Err(Fault::Raised(code));
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes `data` at `addr`, to devices or to `memory`.
+    ///
+    /// Sections going to `memory` are passed to `ram` along with their slice of memory,
+    /// which is expected to write them.
+    pub(crate) fn write(
+        &self, memory: &mut [u8], addr: u32, data: &[u8],
+        mut ram: impl FnMut(&mut [u8], u32, &[u8]) -> Option<u32>
+    ) -> Result<(), Fault> {
+        for segment in self.segments(memory.len(), addr, data.len())? {
+            let data = &data[segment.offset.clone()];
+            let code = match segment.mapping {
+                Some(index) => {
+                    let mapping = &self.mappings[index];
+                    let relative = segment.addr - mapping.range.start;
+                    mapping.device.borrow_mut().write(relative, data)
+                },
+                None => {
+                    let start = segment.addr as usize;
+                    ram(&mut memory[start .. start + data.len()], segment.addr, data)
+                }
+            };
+            if let Some(code) = code {
+                return Err(Fault::Raised(code));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+//! A console device, for printing and reading text over memory-mapped IO.
+//!
+//! The console is usually mapped onto [`RANGE`], with these registers, each one byte wide:
+//!
+//! | Address    | Register | Reading                                     | Writing                      |
+//! |:----------:|:--------:|:-------------------------------------------:|:----------------------------:|
+//! | `0000FF00` | `STDOUT` | Always `00`.                                | Writes the byte to output.   |
+//! | `0000FF01` | `STDERR` | Always `00`.                                | Writes the byte to error.    |
+//! | `0000FF02` | `STDIN`  | Reads a byte of input, or `00` at its end.  | Ignored.                     |
+//! | `0000FF03` | `STATUS` | `01` if the end of input was reached.       | Ignored.                     |
+//!
+//! Multi-byte accesses act on each register in turn, so writing a `u16` at `STDOUT`
+//! prints its first byte and writes its second to `STDERR`.
+//! If the host fails to read or write, an interrupt of code [`IO_ERROR`] is raised.
+//!
+//! ```rust
+//! # use lasagna::console::{self, Console, SharedBuffer};
+//! # use lasagna::emulator::Emulator;
+//! # use lasagna::parser::assemble;
+//! let output = SharedBuffer::new();
+//! let console = Console::new(&b"hi"[..], output.clone(), std::io::sink());
+//! let mut emulator = Emulator::default();
+//! emulator.bus.map(console::RANGE, console).unwrap();
+//!
+//! let program = assemble("
+//!     literal 0xFF02_u32
+//!     read u32
+//!     move
+//!     read u8 [ Echo both bytes of input ]
+//!     left u16
+//!     write u8
+//!     right u16
+//!     read u8
+//!     left u16
+//!     write u8
+//!     right u16
+//!     read u8 [ Then hit the end of input ]
+//!     right u8
+//!     read u8
+//! ").unwrap();
+//! emulator.memory[0x20000 .. 0x20000 + program.len()].copy_from_slice(&program);
+//! for _ in 0..14 {
+//!     assert_eq!(emulator.step(), None);
+//! }
+//! assert_eq!(emulator.val1[0], 1);
+//! assert_eq!(output.contents(), b"hi");
+//! ```
+use std::cell::RefCell;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::bus::Device;
+
+/// Where the console is usually mapped.
+pub const RANGE: Range<u32> = 0xFF00 .. 0xFF04;
+
+/// Writing a byte here prints it to the console's output.
+pub const STDOUT: u32 = 0;
+/// Writing a byte here prints it to the console's error output.
+pub const STDERR: u32 = 1;
+/// Reading a byte here reads it from the console's input, or `00` at the end of input.
+pub const STDIN: u32 = 2;
+/// Reading a byte here gives `01` if the end of the console's input was reached.
+pub const STATUS: u32 = 3;
+
+/// The interrupt code raised when the host fails to read or write.
+pub const IO_ERROR: u32 = 0xE0;
+
+/// A console device, reading input from `R` and writing output to `O` and errors to `E`.
+/// See the [module documentation](self) for its registers.
+pub struct Console<R, O, E> {
+    input: R,
+    output: O,
+    error: E,
+    eof: bool
+}
+
+impl<R: Read, O: Write, E: Write> Console<R, O, E> {
+    /// Creates a console with the given input, output and error output.
+    pub fn new(input: R, output: O, error: E) -> Self {
+        Self {input, output, error, eof: false}
+    }
+
+    fn read_input(&mut self) -> io::Result<u8> {
+        // Anything written so far might be a prompt for this input
+        self.output.flush()?;
+        let mut byte = [0];
+        loop {
+            match self.input.read(&mut byte) {
+                Ok(0) => {
+                    self.eof = true;
+                    return Ok(0);
+                },
+                Ok(_) => return Ok(byte[0]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err)
+            }
+        }
+    }
+}
+
+impl Console<io::Stdin, io::Stdout, io::Stderr> {
+    /// Creates a console connected to the host's standard input, output and error.
+    pub fn stdio() -> Self {
+        Self::new(io::stdin(), io::stdout(), io::stderr())
+    }
+}
+
+impl<R: Read, O: Write, E: Write> Device for Console<R, O, E> {
+    fn read(&mut self, addr: u32, buf: &mut [u8]) -> Option<u32> {
+        for (register, byte) in (addr..).zip(buf) {
+            *byte = match register {
+                STDIN => match self.read_input() {
+                    Ok(byte) => byte,
+                    Err(_) => return Some(IO_ERROR)
+                },
+                STATUS => self.eof as u8,
+                _ => 0
+            };
+        }
+        None
+    }
+
+    fn write(&mut self, addr: u32, data: &[u8]) -> Option<u32> {
+        for (register, byte) in (addr..).zip(data) {
+            let result = match register {
+                STDOUT => self.output.write_all(&[*byte]),
+                STDERR => self.error.write_all(&[*byte]),
+                _ => Ok(())
+            };
+            if result.is_err() {
+                return Some(IO_ERROR);
+            }
+        }
+        None
+    }
+}
+
+/// A buffer that can be shared with a [`Console`] as its output,
+/// so that the output can be read after the console is mapped onto a bus.
+///
+/// Clones of a buffer share their contents.
+#[derive(Debug, Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    /// Creates an empty buffer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Copies out everything written so far.
+    pub fn contents(&self) -> Vec<u8> {
+        self.0.borrow().clone()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
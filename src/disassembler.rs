@@ -0,0 +1,128 @@
+//! Turns bytecode back into Lasagna's textual representation.
+//!
+//! The rendered text reassembles into the exact same bytes.
+//!
+//! ```rust
+//! # use lasagna::parser::assemble;
+//! # use lasagna::disassembler::{disassemble, render};
+//! let program = assemble("literal 'Hi!'\nread u8\ncast u8 float\nbreak").unwrap();
+//! let instructions = disassemble(&program, 0x20000);
+//! assert_eq!(instructions[1].to_string(), "read u8");
+//! assert_eq!(render(&instructions), "\
+//! [00020000] literal 'Hi!'
+//! [00020009] read u8
+//! [0002000A] cast u8 float
+//! [0002000B] break
+//! ");
+//! assert_eq!(assemble(&render(&instructions)).unwrap(), program);
+//! ```
+use std::fmt;
+
+use crate::isa::Instruction;
+
+/// A single instruction decoded from bytecode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedInstruction {
+    /// The address of the instruction's first byte.
+    pub address: u32,
+    /// The bytes the instruction was decoded from.
+    pub bytes: Vec<u8>,
+    /// The decoded instruction, or `None` if it was a `literal` whose data ran past the end.
+    pub instruction: Option<Instruction>
+}
+
+/// Decodes bytecode into a list of instructions,
+/// where `base_addr` is the address the bytecode starts at.
+///
+/// ```rust
+/// # use lasagna::isa::{Instruction, Type};
+/// # use lasagna::disassembler::disassemble;
+/// let instructions = disassemble(&[0x00, 0x20, 0x00, 0x00, 0x00, 0x01, 0xFF, 0x81], 0x100);
+/// assert_eq!(instructions.len(), 3);
+/// assert_eq!(instructions[1].address, 0x101);
+/// assert_eq!(instructions[1].instruction, Some(Instruction::Literal(vec![0xFF])));
+/// assert_eq!(instructions[2].instruction, Some(Instruction::Add(Type::I8)));
+/// ```
+pub fn disassemble(bytes: &[u8], base_addr: u32) -> Vec<DecodedInstruction> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let instruction = decode(&bytes[offset..], base_addr.wrapping_add(offset as u32));
+        offset += instruction.bytes.len();
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+/// Decodes the instruction at `addr` in memory, along with up to `before` instructions before it
+/// and `after` instructions after it.
+///
+/// Instructions can't be decoded backwards, so the ones before are found by decoding from `start`.
+/// If that doesn't land on `addr`, there are none before it.
+///
+/// ```rust
+/// # use lasagna::parser::assemble;
+/// # use lasagna::disassembler::disassemble_around;
+/// let program = assemble("literal 'Hi!'\nread u8\ncast u8 float\nbreak").unwrap();
+/// let instructions = disassemble_around(&program, 0, 10, 1, 5);
+/// let addresses: Vec<u32> = instructions.iter().map(|instruction| instruction.address).collect();
+/// assert_eq!(addresses, [9, 10, 11]);
+/// assert_eq!(disassemble_around(&program, 0, 1, 1, 0)[0].address, 1);
+/// ```
+pub fn disassemble_around(memory: &[u8], start: u32, addr: u32, before: usize, after: usize) -> Vec<DecodedInstruction> {
+    let (start, addr) = (start as usize, addr as usize);
+    if addr >= memory.len() {
+        return Vec::new();
+    }
+    let mut instructions = if start <= addr { disassemble(&memory[start..addr], start as u32) } else { Vec::new() };
+    // A `literal` cut off at `addr` would look like it ends there
+    let end = instructions.last().map_or(Some(start), |last| {
+        last.instruction.as_ref().map(|_| last.address as usize + last.bytes.len())
+    });
+    if end != Some(addr) {
+        instructions.clear();
+    }
+    instructions.drain(..instructions.len().saturating_sub(before));
+
+    let total = instructions.len() + after + 1;
+    let mut offset = addr;
+    while offset < memory.len() && instructions.len() < total {
+        let next = decode(&memory[offset..], offset as u32);
+        offset += next.bytes.len();
+        instructions.push(next);
+    }
+    instructions
+}
+
+/// Decodes the first instruction of some bytecode, or all of it if it's a truncated `literal`.
+fn decode(bytes: &[u8], address: u32) -> DecodedInstruction {
+    let (instruction, length) = match Instruction::decode(bytes) {
+        Some((instruction, length)) => (Some(instruction), length),
+        None => (None, bytes.len())
+    };
+    DecodedInstruction {address, bytes: bytes[..length].to_vec(), instruction}
+}
+
+impl fmt::Display for DecodedInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.instruction {
+            Some(instruction) => write!(f, "{instruction}"),
+            None => {
+                f.write_str("[ truncated literal:")?;
+                for b in &self.bytes {
+                    write!(f, " {b:02X}")?;
+                }
+                f.write_str(" ]")
+            }
+        }
+    }
+}
+
+/// Renders decoded instructions as text, one per line, with their addresses in comments.
+pub fn render(instructions: &[DecodedInstruction]) -> String {
+    let mut text = String::new();
+    for instruction in instructions {
+        text += &format!("[{:08X}] {instruction}\n", instruction.address);
+    }
+    text
+}
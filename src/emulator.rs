@@ -1,6 +1,16 @@
 mod structures {
 
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::rc::Rc;
+
+    use crate::bus::{Bus, Fault};
     use crate::constants;
+    use crate::isa::{Instruction, Type};
+    use crate::breakpoints::Breakpoints;
+    use crate::journal::Journal;
+    use crate::trace::MemoryAccess;
 
     macro_rules! overflowing {
         ($self: ident, $ty: ident, $int: ident, $float: tt, $b1: expr, $b2: expr) => {
@@ -27,12 +37,6 @@ mod structures {
     }
 
     macro_rules! as_convert {
-        ($n: ident, $self: ident; f32 = $size: literal) => {
-            let value = ($n as f32).to_le_bytes();
-            for i in 0..$size {
-                $self.val1[i] = value[i];
-            }
-        };
         ($n: ident, $self: ident; $to: ty = $size: literal) => {
             let value = ($n as $to).to_be_bytes();
             for i in 0..$size {
@@ -54,28 +58,27 @@ mod structures {
     }
 
     impl Value {
-        fn from_bytes(value: &[u8], ty: u8) -> Self {
+        fn from_bytes(value: &[u8], ty: Type) -> Self {
             use Value::*;
-            match ty & constants::TYPE {
-                0b1000.. => unreachable!(),
-                0b000 => U8(value[0]),
-                0b001 => I8(value[0] as i8),
-                0b010 => U16(u16::from_be_bytes(
+            match ty {
+                Type::U8 => U8(value[0]),
+                Type::I8 => I8(value[0] as i8),
+                Type::U16 => U16(u16::from_be_bytes(
                     value[0..2].try_into().unwrap()
                 )),
-                0b011 => I16(i16::from_be_bytes(
+                Type::I16 => I16(i16::from_be_bytes(
                     value[0..2].try_into().unwrap()
                 )),
-                0b100 => U32(u32::from_be_bytes(
+                Type::U32 => U32(u32::from_be_bytes(
                     value.try_into().unwrap()
                 )),
-                0b101 => I32(i32::from_be_bytes(
+                Type::I32 => I32(i32::from_be_bytes(
                     value.try_into().unwrap()
                 )),
-                0b110 => Float(f32::from_le_bytes(
+                Type::Float => Float(f32::from_be_bytes(
                     value.try_into().unwrap()
                 )),
-                0b111 => Bool(value[0])
+                Type::Bool => Bool(value[0])
             }
         }
 
@@ -94,22 +97,18 @@ mod structures {
                 },
                 U32(v) => (v.to_be_bytes(), 4),
                 I32(v) => (v.to_be_bytes(), 4),
-                Float(v) => (v.to_le_bytes(), 4),
+                Float(v) => (v.to_be_bytes(), 4),
                 Bool(v) => ([v, 0, 0, 0], 1)
             }
         }
     }
 
-    /// An instance of an emulator. You should probably store this on the heap.
+    /// An instance of an emulator, with its memory stored on the heap.
     /// The emulator can be iterated over to get individual step results.
     ///
     /// ```rust
-    /// # extern crate alloc;
-    /// # use alloc::boxed::Box;
     /// # use lasagna::emulator::Emulator;
-    /// let emulator = Box::new(
-    ///     Emulator::<0x100000>::default()
-    /// );
+    /// let emulator = Emulator::default();
     ///
     /// for result in emulator {
     ///     if let Some(interrupt) = result {
@@ -119,129 +118,724 @@ mod structures {
     /// }
     /// ```
     #[derive(Clone)]
-    pub struct Emulator<const SIZE: usize> {
+    pub struct Emulator {
         pub val1:   [u8; 4],
         pub val2:   [u8; 4],
         pub ptr:    u32,
         pub cur:    u32,
         pub stat:   u32,
-        pub memory: [u8; SIZE],
-        pub debugger: Option<fn(&mut Self) -> Option<u32>>,
-        pub callback: Option<fn(&mut [u8], u32, &[u8]) -> Option<u32>>
+        pub memory: Box<[u8]>,
+        /// The devices mapped onto memory. See [`crate::bus`].
+        pub bus: Bus,
+        pub debugger: Option<Debugger>,
+        pub callback: Option<Callback>,
+        pub read_callback: Option<ReadCallback>,
+        /// Handlers for `interrupt` instructions, by their code. See [`Emulator::with_syscall`].
+        pub syscalls: HashMap<u32, Syscall>,
+        pub vector_table: Option<VectorTable>,
+        /// The record of steps taken, for stepping backwards. See [`crate::journal`].
+        pub journal: Option<Journal>,
+        /// The memory accesses made by the step being traced. See [`crate::trace`].
+        pub(crate) accesses: Option<Vec<MemoryAccess>>,
+        /// Where [`Emulator::run`] stops. See [`crate::breakpoints`].
+        pub breakpoints: Breakpoints,
+        /// Whether the step being taken was halted by a syscall, which the vector table doesn't handle.
+        syscall_halted: bool
     }
 
-    impl<const SIZE: usize> Default for Emulator<SIZE> {
+    /// Creates an emulator with the default memory size of 1 MiB.
+    impl Default for Emulator {
         fn default() -> Self {
-            Self::new([0; 4], [0; 4], 0, 0x20000, 0)
+            Self::builder().build().expect("default memory size should be valid")
+        }
+    }
+
+    /// The result of a single step, being `Some` if the emulator was interrupted.
+    pub type StepResult = Option<Interrupt>;
+
+    /// The kind of memory access that went out of bounds.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+    pub enum Access {
+        /// Reading memory at `PTR`, including jump targets.
+        Read,
+        /// Writing memory at `PTR`.
+        Write,
+        /// Fetching the instruction at `CUR`.
+        Execute,
+        /// Moving `PTR` or `CUR` past either end of the address space.
+        Pointer
+    }
+
+    impl fmt::Display for Access {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(match self {
+                Self::Read => "read",
+                Self::Write => "write",
+                Self::Execute => "execute",
+                Self::Pointer => "move pointer"
+            })
         }
     }
 
-    pub type StepResult = Option<u32>;
+    /// Why the emulator stopped, returned by [`Emulator::step`].
+    ///
+    /// Every variant holds the values of `CUR` and `PTR` from when it was raised,
+    /// with `CUR` pointing at the instruction that raised it.
+    ///
+    /// ```rust
+    /// # use lasagna::emulator::{Emulator, Interrupt};
+    /// # use lasagna::parser::assemble;
+    /// let program = assemble("literal 2_u32\nread u32\ninterrupt").unwrap();
+    /// let mut emulator = Emulator::default();
+    /// emulator.memory[0x20000 .. 0x20000 + program.len()].copy_from_slice(&program);
+    ///
+    /// let interrupt = emulator.find_map(|result| result).unwrap();
+    /// assert_eq!(interrupt, Interrupt::User {code: 2, cur: 0x2000A, ptr: 0});
+    /// // Indistinguishable from a stack overflow by its code alone
+    /// assert_eq!(interrupt.code(), 2);
+    /// ```
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+    pub enum Interrupt {
+        /// `CUR` reached the end of memory.
+        Halt {cur: u32, ptr: u32},
+        /// Memory was accessed out of bounds at `addr`.
+        OutOfBounds {addr: u32, access: Access, cur: u32, ptr: u32},
+        /// A value was pushed onto a full stack.
+        StackOverflow {cur: u32, ptr: u32},
+        /// A value was popped from an empty stack.
+        StackUnderflow {cur: u32, ptr: u32},
+        /// A value was divided by zero.
+        DivideByZero {cur: u32, ptr: u32},
+        /// The program raised an interrupt with the `interrupt` instruction.
+        User {code: u32, cur: u32, ptr: u32},
+        /// The attached debugger raised an interrupt. See [`Emulator::with_debugger`].
+        Debugger {code: u32, cur: u32, ptr: u32},
+        /// A device on the [`Emulator::bus`] or the memory write callback raised an interrupt.
+        Device {code: u32, cur: u32, ptr: u32}
+    }
+
+    impl Interrupt {
+        /// The legacy numeric code of this interrupt.
+        ///
+        /// Implicit interrupts map to `0` for halting, `1` for out of bounds accesses,
+        /// `2` for stack overflows, `3` for stack underflows, and `4` for dividing by zero.
+        /// Any other interrupt maps to the code it was raised with.
+        pub fn code(&self) -> u32 {
+            match *self {
+                Self::Halt {..} => 0,
+                Self::OutOfBounds {..} => 1,
+                Self::StackOverflow {..} => 2,
+                Self::StackUnderflow {..} => 3,
+                Self::DivideByZero {..} => 4,
+                Self::User {code, ..} | Self::Debugger {code, ..} | Self::Device {code, ..} => code
+            }
+        }
+
+        /// The value of `CUR` when this interrupt was raised.
+        pub fn cur(&self) -> u32 {
+            match *self {
+                Self::Halt {cur, ..} | Self::OutOfBounds {cur, ..}
+                | Self::StackOverflow {cur, ..} | Self::StackUnderflow {cur, ..}
+                | Self::DivideByZero {cur, ..} | Self::User {cur, ..}
+                | Self::Debugger {cur, ..} | Self::Device {cur, ..} => cur
+            }
+        }
+
+        /// The value of `PTR` when this interrupt was raised.
+        pub fn ptr(&self) -> u32 {
+            match *self {
+                Self::Halt {ptr, ..} | Self::OutOfBounds {ptr, ..}
+                | Self::StackOverflow {ptr, ..} | Self::StackUnderflow {ptr, ..}
+                | Self::DivideByZero {ptr, ..} | Self::User {ptr, ..}
+                | Self::Debugger {ptr, ..} | Self::Device {ptr, ..} => ptr
+            }
+        }
+
+        /// Whether this interrupt was a fault raised by the emulator,
+        /// rather than halting or being raised on purpose.
+        pub fn is_fault(&self) -> bool {
+            matches!(self,
+                Self::OutOfBounds {..} | Self::StackOverflow {..}
+                | Self::StackUnderflow {..} | Self::DivideByZero {..}
+            )
+        }
+    }
+
+    impl From<Interrupt> for u32 {
+        fn from(interrupt: Interrupt) -> u32 {
+            interrupt.code()
+        }
+    }
+
+    impl fmt::Display for Interrupt {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Self::Halt {..} => f.write_str("halted at the end of memory"),
+                Self::OutOfBounds {addr, access, ..} =>
+                    write!(f, "out of bounds {access} at {addr:#010X}"),
+                Self::StackOverflow {..} => f.write_str("stack overflow"),
+                Self::StackUnderflow {..} => f.write_str("stack underflow"),
+                Self::DivideByZero {..} => f.write_str("divide by zero"),
+                Self::User {code, ..} => write!(f, "interrupt {code}"),
+                Self::Debugger {code, ..} => write!(f, "debugger interrupt {code}"),
+                Self::Device {code, ..} => write!(f, "device interrupt {code}")
+            }?;
+            write!(f, " (CUR {:#010X}, PTR {:#010X})", self.cur(), self.ptr())
+        }
+    }
+
+    /// A memory write callback. See [`Emulator::with_callback`].
+    ///
+    /// Callbacks are shared between clones of an emulator, along with anything they capture.
+    pub type Callback = Rc<RefCell<dyn FnMut(&mut [u8], u32, &[u8]) -> Option<u32>>>;
+
+    /// A memory read callback. See [`Emulator::with_read_callback`].
+    ///
+    /// Callbacks are shared between clones of an emulator, along with anything they capture.
+    pub type ReadCallback = Rc<RefCell<dyn FnMut(&[u8], u32, &mut [u8]) -> Option<u32>>>;
+
+    /// A syscall handler, run on `interrupt` instructions. See [`Emulator::with_syscall`].
+    ///
+    /// Handlers are shared between clones of an emulator, along with anything they capture.
+    pub type Syscall = Rc<RefCell<dyn FnMut(&mut Emulator) -> SyscallResult>>;
+
+    /// What to do after a syscall is handled.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+    pub enum SyscallResult {
+        /// Carry on with the instruction after the `interrupt`.
+        Resume,
+        /// Raise an [`Interrupt::User`] with the code in `VAL1`, as if the syscall wasn't handled.
+        Halt,
+        /// Raise an interrupt as if the `interrupt` instruction caused it,
+        /// like a fault from [`Emulator::write_ptr`].
+        Raise(Interrupt)
+    }
+
+    /// A table in memory of handlers that the program provides for interrupts.
+    ///
+    /// The table is an array of `len` big-endian u32 addresses at `address`.
+    /// The first [`VectorTable::USER`] entries are for faults, indexed by their [code](Interrupt::code):
+    /// `1` for out of bounds accesses, `2` for stack overflows, `3` for stack underflows,
+    /// and `4` for dividing by zero, with the rest reserved.
+    /// User and device interrupts come after them, at `USER` plus the code they were raised with,
+    /// so a program can't raise a fault's handler with `interrupt`. See [`VectorTable::index`].
+    ///
+    /// When an interrupt with an entry in the table is raised, `CUR` is pushed onto the stack,
+    /// and execution continues at the address in the entry. An address of `0` means no handler.
+    ///
+    /// The handler returns by raising an interrupt with the code [`VectorTable::RETURN`],
+    /// which pops `CUR` off the stack, and continues after the instruction that raised the interrupt.
+    ///
+    /// Faults, user interrupts that no syscall halted on, and device interrupts can all be handled,
+    /// but halting and debugger interrupts can't.
+    /// If pushing `CUR` overflows the stack, the original interrupt is returned from [`Emulator::step`].
+    ///
+    /// ```rust
+    /// # use lasagna::emulator::{Emulator, Interrupt};
+    /// # use lasagna::parser::assemble;
+    /// let program = assemble("
+    ///     literal 0_u32
+    ///     read u32
+    ///     swap
+    ///     divide u32
+    ///     literal 9_u32
+    ///     read u32
+    ///     interrupt
+    /// ").unwrap();
+    /// let handler = assemble("
+    ///     literal 0xFFFFFFFF_u32 [ Ignore the division and return ]
+    ///     read u32
+    ///     interrupt
+    /// ").unwrap();
+    ///
+    /// let mut emulator = Emulator::builder().vector_table(0x1000, 5).build().unwrap();
+    /// emulator.memory[0x20000 .. 0x20000 + program.len()].copy_from_slice(&program);
+    /// emulator.memory[0x30000 .. 0x30000 + handler.len()].copy_from_slice(&handler);
+    /// // Handle dividing by zero, with a code of 4
+    /// emulator.memory[0x1010 .. 0x1014].copy_from_slice(&0x30000_u32.to_be_bytes());
+    ///
+    /// let interrupt = emulator.find_map(|result| result).unwrap();
+    /// assert!(matches!(interrupt, Interrupt::User {code: 9, ..}));
+    /// ```
+    ///
+    /// Raising an interrupt with the code of a fault reaches a different handler than the fault:
+    /// ```rust
+    /// # use lasagna::emulator::{Emulator, Interrupt, VectorTable};
+    /// # use lasagna::parser::assemble;
+    /// let run = |source: &str| {
+    ///     let program = assemble(source).unwrap();
+    ///     let divide_handler = assemble("literal 0xD_u32\nread u32\ninterrupt").unwrap();
+    ///     let user_handler = assemble("literal 0xE_u32\nread u32\ninterrupt").unwrap();
+    ///
+    ///     let mut emulator = Emulator::builder().vector_table(0x1000, VectorTable::USER + 5).build().unwrap();
+    ///     emulator.memory[0x20000 .. 0x20000 + program.len()].copy_from_slice(&program);
+    ///     emulator.memory[0x30000 .. 0x30000 + divide_handler.len()].copy_from_slice(&divide_handler);
+    ///     emulator.memory[0x31000 .. 0x31000 + user_handler.len()].copy_from_slice(&user_handler);
+    ///     for (interrupt, handler) in [
+    ///         (Interrupt::DivideByZero {cur: 0, ptr: 0}, 0x30000_u32),
+    ///         (Interrupt::User {code: 4, cur: 0, ptr: 0}, 0x31000)
+    ///     ] {
+    ///         let entry = 0x1000 + VectorTable::index(interrupt).unwrap() as usize * 4;
+    ///         emulator.memory[entry .. entry + 4].copy_from_slice(&handler.to_be_bytes());
+    ///     }
+    ///     emulator.find_map(|result| result).unwrap().code()
+    /// };
+    ///
+    /// assert_eq!(run("literal 0_u32\nread u32\nswap\ndivide u32"), 0xD);
+    /// assert_eq!(run("literal 4_u32\nread u32\ninterrupt"), 0xE);
+    /// ```
+    ///
+    /// Syscalls that halt, like [`EXIT`](crate::syscall::EXIT), halt even if there's a handler for their code:
+    /// ```rust
+    /// # use lasagna::emulator::{Emulator, Interrupt, VectorTable};
+    /// # use lasagna::parser::assemble;
+    /// # use lasagna::syscall;
+    /// let program = assemble("
+    ///     literal 3_u32
+    ///     read u32
+    ///     swap
+    ///     literal 0x13_u32 [ EXIT ]
+    ///     read u32
+    ///     interrupt
+    /// ").unwrap();
+    /// let emulator = Emulator::builder().vector_table(0x1000, VectorTable::USER + 4).build().unwrap();
+    /// let mut emulator = syscall::standard(emulator, std::io::empty(), std::io::sink());
+    /// emulator.memory[0x20000 .. 0x20000 + program.len()].copy_from_slice(&program);
+    /// let entry = 0x1000 + (VectorTable::USER as usize + 3) * 4;
+    /// emulator.memory[entry .. entry + 4].copy_from_slice(&0x30000_u32.to_be_bytes());
+    ///
+    /// let interrupt = emulator.find_map(|result| result).unwrap();
+    /// assert!(matches!(interrupt, Interrupt::User {code: 3, cur: 0x20015, ..}));
+    /// ```
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+    pub struct VectorTable {
+        pub address: u32,
+        pub len: u32
+    }
+
+    impl VectorTable {
+        /// The interrupt code for returning from a handler.
+        pub const RETURN: u32 = 0xFFFF_FFFF;
+
+        /// The index of the entry for user and device interrupts with a code of `0`.
+        /// The entries before it are for faults.
+        pub const USER: u32 = 8;
+
+        /// The index of the entry for an interrupt, or `None` if it can't be handled.
+        pub fn index(interrupt: Interrupt) -> Option<u64> {
+            match interrupt {
+                Interrupt::Halt {..} | Interrupt::Debugger {..} => None,
+                Interrupt::OutOfBounds {..} | Interrupt::StackOverflow {..}
+                    | Interrupt::StackUnderflow {..} | Interrupt::DivideByZero {..} => Some(interrupt.code() as u64),
+                Interrupt::User {code, ..} | Interrupt::Device {code, ..} => Some(Self::USER as u64 + code as u64)
+            }
+        }
+    }
+
+    /// A debugger, run on `break` instructions. See [`Emulator::with_debugger`].
+    ///
+    /// Debuggers are shared between clones of an emulator, along with anything they capture.
+    pub type Debugger = Rc<RefCell<dyn FnMut(&mut Emulator) -> Option<u32>>>;
+
+    /// An error raised when an [`EmulatorBuilder`] is given an invalid configuration.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum BuildError {
+        /// The memory is too small to hold the stack, with the given size.
+        MemoryTooSmall(usize),
+        /// The memory is too large to be addressed by a u32, with the given size.
+        MemoryTooLarge(usize)
+    }
+
+    impl fmt::Display for BuildError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Self::MemoryTooSmall(size) =>
+                    write!(f, "memory of {size:#X} bytes is too small to contain the stack"),
+                Self::MemoryTooLarge(size) =>
+                    write!(f, "memory of {size:#X} bytes won't fit into a u32")
+            }
+        }
+    }
+
+    impl std::error::Error for BuildError {}
+
+    /// Configures and creates an [`Emulator`]. See [`Emulator::builder`].
+    #[derive(Debug, Clone)]
+    pub struct EmulatorBuilder {
+        memory_size: usize,
+        val1: [u8; 4],
+        val2: [u8; 4],
+        ptr: u32,
+        cur: u32,
+        stat: u32,
+        vector_table: Option<VectorTable>
+    }
+
+    impl EmulatorBuilder {
+        /// Sets the size of the emulator's memory in bytes. Defaults to 1 MiB.
+        pub fn memory_size(mut self, size: usize) -> Self {
+            self.memory_size = size;
+            self
+        }
+
+        /// Sets the initial value of `VAL1`. Defaults to zero.
+        pub fn val1(mut self, val1: [u8; 4]) -> Self {
+            self.val1 = val1;
+            self
+        }
+
+        /// Sets the initial value of `VAL2`. Defaults to zero.
+        pub fn val2(mut self, val2: [u8; 4]) -> Self {
+            self.val2 = val2;
+            self
+        }
+
+        /// Sets the initial value of `PTR`. Defaults to `0`.
+        pub fn ptr(mut self, ptr: u32) -> Self {
+            self.ptr = ptr;
+            self
+        }
+
+        /// Sets the initial value of `CUR`. Defaults to `0x20000`.
+        pub fn cur(mut self, cur: u32) -> Self {
+            self.cur = cur;
+            self
+        }
+
+        /// Sets the initial value of `STAT`. Defaults to `0`.
+        pub fn stat(mut self, stat: u32) -> Self {
+            self.stat = stat;
+            self
+        }
+
+        /// Sets the interrupt vector table, with `len` entries at `address`. Defaults to none.
+        pub fn vector_table(mut self, address: u32, len: u32) -> Self {
+            self.vector_table = Some(VectorTable {address, len});
+            self
+        }
 
-    impl<const SIZE: usize> Emulator<SIZE> {
-        /// Creates a new instance of an emulator, with memory zeroed out.
+        /// Creates the emulator, with memory zeroed out.
         ///
-        /// # Panics
-        /// * The memory is too small to hold the stack (smaller than `0x20000`.)
+        /// # Errors
+        /// * The memory is too small to hold the stack (no larger than `0x20000`.)
         /// * The memory is larger than a u32.
         ///
-        /// ```should_panic
-        /// # use lasagna::emulator::Emulator;
-        /// let mut emulator = Emulator::<0>::default();
+        /// ```rust
+        /// # use lasagna::emulator::{Emulator, BuildError};
+        /// assert!(Emulator::builder().memory_size(16 << 20).build().is_ok());
+        /// assert_eq!(
+        ///     Emulator::builder().memory_size(0).build().err(),
+        ///     Some(BuildError::MemoryTooSmall(0))
+        /// );
         /// ```
-        /// ```should_panic
+        pub fn build(self) -> Result<Emulator, BuildError> {
+            if self.memory_size <= 0x20000 {
+                return Err(BuildError::MemoryTooSmall(self.memory_size));
+            }
+            if self.memory_size > u32::MAX as usize {
+                return Err(BuildError::MemoryTooLarge(self.memory_size));
+            }
+            Ok(Emulator {
+                val1: self.val1,
+                val2: self.val2,
+                ptr: self.ptr,
+                cur: self.cur,
+                stat: self.stat,
+                memory: vec![0; self.memory_size].into_boxed_slice(),
+                bus: Bus::new(),
+                debugger: None,
+                callback: None,
+                read_callback: None,
+                syscalls: HashMap::new(),
+                vector_table: self.vector_table,
+                journal: None,
+                accesses: None,
+                breakpoints: Breakpoints::default(),
+                syscall_halted: false
+            })
+        }
+    }
+
+    impl Emulator {
+        /// Starts configuring an emulator.
+        ///
+        /// ```rust
         /// # use lasagna::emulator::Emulator;
-        /// let mut emulator = Emulator::<{(u32::MAX as usize) + 1}>::default();
+        /// let emulator = Emulator::builder()
+        ///     .memory_size(16 << 20)
+        ///     .ptr(0x100)
+        ///     .build()
+        ///     .unwrap();
+        /// assert_eq!(emulator.memory.len(), 16 << 20);
+        /// assert_eq!(emulator.cur, 0x20000);
         /// ```
-        pub fn new(val1: [u8; 4], val2: [u8; 4], ptr: u32, cur: u32, stat: u32) -> Self {
-            assert!(SIZE > 0x20000, "memory must be large enough to contain stack");
-            assert!(SIZE <= u32::MAX as usize, "memory size won't fit into a u32");
-            Self {
-                val1,
-                val2,
-                ptr,
-                cur,
-                stat,
-                memory: [0; SIZE],
-                debugger: None,
-                callback: None
+        pub fn builder() -> EmulatorBuilder {
+            EmulatorBuilder {
+                memory_size: 0x100000,
+                val1: [0; 4],
+                val2: [0; 4],
+                ptr: 0,
+                cur: constants::PROGRAM_START,
+                stat: 0,
+                vector_table: None
             }
         }
 
-        /// Attach a debugging function to this emulator.
-        /// Returns a potential interrupt code.
+        /// Handle `interrupt` instructions with the given code on the host, instead of interrupting.
+        /// The handler can access the emulator however it likes, and then resumes or halts.
+        ///
+        /// Once it resumes, execution continues after `CUR`, so a handler that moves `CUR`
+        /// should point it at the instruction just before where it wants to go, like a jump.
+        /// If it halts, an [`Interrupt::User`] is raised with whatever code is in `VAL1` then.
+        ///
+        /// See [`crate::syscall`] for the standard syscalls.
         ///
         /// # Examples
         /// ```rust
-        /// # use lasagna::emulator::Emulator;
-        /// fn debugger<const SIZE: usize>(emulator: &mut Emulator<SIZE>) -> Option<u32> {
-        ///     println!("VAL1: {:02X?}", emulator.val1);
-        ///     None
-        /// }
+        /// # use lasagna::emulator::{Emulator, Interrupt, SyscallResult};
+        /// # use lasagna::parser::assemble;
+        /// // Doubles `VAL2`, halting once it overflows
+        /// let mut emulator = Emulator::default()
+        ///     .with_syscall(0x10, |emulator| {
+        ///         match u32::from_be_bytes(emulator.val2).checked_mul(2) {
+        ///             Some(doubled) => {
+        ///                 emulator.val2 = doubled.to_be_bytes();
+        ///                 SyscallResult::Resume
+        ///             },
+        ///             None => SyscallResult::Halt
+        ///         }
+        ///     });
         ///
-        /// let mut emu = Emulator::<0x100000>::default()
-        ///     .with_debugger(debugger);
+        /// let program = assemble("
+        ///     literal 0x10_u32
+        ///     read u32
+        ///     move
+        ///     label double
+        ///     interrupt
+        ///     jump double
+        /// ").unwrap();
+        /// emulator.memory[0x20000 .. 0x20000 + program.len()].copy_from_slice(&program);
+        /// emulator.val2 = [0, 0, 0, 1];
+        /// let interrupt = emulator.find_map(|result| result).unwrap();
+        /// assert!(matches!(interrupt, Interrupt::User {code: 0x10, ..}));
+        /// assert_eq!(emulator.val2, [0x80, 0, 0, 0]);
+        /// ```
+        pub fn with_syscall(mut self, code: u32, handler: impl FnMut(&mut Self) -> SyscallResult + 'static) -> Self {
+            self.syscalls.insert(code, Rc::new(RefCell::new(handler)));
+            self
+        }
+
+        /// Attach a debugging function to this emulator, which is run on `break` instructions.
+        /// Returns a potential interrupt code.
         ///
+        /// The debugger can't be re-entered, so it mustn't step over a `break` itself.
+        ///
+        /// # Examples
+        /// ```rust
+        /// # use std::{cell::RefCell, rc::Rc};
+        /// # use lasagna::emulator::{Emulator, Interrupt};
+        /// # use lasagna::parser::assemble;
+        /// let breaks = Rc::new(RefCell::new(Vec::new()));
+        /// let log = breaks.clone();
+        /// let mut emulator = Emulator::default()
+        ///     .with_debugger(move |emulator| {
+        ///         log.borrow_mut().push(emulator.cur);
+        ///         (log.borrow().len() == 2).then_some(7)
+        ///     });
+        ///
+        /// let program = assemble("break\nnoop\nbreak").unwrap();
+        /// emulator.memory[0x20000 .. 0x20000 + program.len()].copy_from_slice(&program);
+        /// let interrupt = emulator.find_map(|result| result);
+        /// assert_eq!(interrupt, Some(Interrupt::Debugger {code: 7, cur: 0x20002, ptr: 0}));
+        /// assert_eq!(*breaks.borrow(), [0x20000, 0x20002]);
         /// ```
-        pub fn with_debugger(mut self, function: fn(&mut Self) -> Option<u32>) -> Self {
-            self.debugger = Some(function);
+        pub fn with_debugger(mut self, function: impl FnMut(&mut Self) -> Option<u32> + 'static) -> Self {
+            self.debugger = Some(Rc::new(RefCell::new(function)));
             self
         }
 
-        /// Attach a memory write callback to this emulator.
-        /// This is useful for simulating memory-mapped IO.
+        /// Attach a memory write callback to this emulator, which is given every write to RAM
+        /// along with its slice of memory and address.
+        /// Writes to devices mapped onto the [`Emulator::bus`] don't go through it.
         ///
         /// Note that if a callback is attached, memory isn't written automatically!
         /// You need to write in your callback.
         ///
         /// # Examples
         /// ```rust
+        /// # use std::{cell::RefCell, rc::Rc};
         /// # use lasagna::emulator::Emulator;
-        /// fn callback(mem_slice: &mut [u8], pointer: u32, value: &[u8]) -> Option<u32> {
-        ///     println!("Writing {:02X?} at {}", value, pointer);
-        ///     for i in 0..mem_slice.len() {
-        ///        mem_slice[i] = value[i];
-        ///     }
-        ///     None
+        /// # use lasagna::parser::assemble;
+        /// let output = Rc::new(RefCell::new(Vec::new()));
+        /// let writes = output.clone();
+        /// let mut emulator = Emulator::default()
+        ///     .with_callback(move |mem_slice, pointer, value| {
+        ///         writes.borrow_mut().push((pointer, value.to_vec()));
+        ///         mem_slice.copy_from_slice(value);
+        ///         None
+        ///     });
+        ///
+        /// let program = assemble("literal 0x1234_u16\nread u16\nright u8\nwrite u8").unwrap();
+        /// emulator.memory[0x20000 .. 0x20000 + program.len()].copy_from_slice(&program);
+        /// for _ in 0..4 {
+        ///     assert_eq!(emulator.step(), None);
         /// }
+        /// assert_eq!(*output.borrow(), [(0, vec![0x12, 0x34]), (1, vec![0x12])]);
+        /// assert_eq!(&emulator.memory[..2], &[0x12, 0x12]);
+        /// ```
+        pub fn with_callback(mut self, function: impl FnMut(&mut [u8], u32, &[u8]) -> Option<u32> + 'static) -> Self {
+            self.callback = Some(Rc::new(RefCell::new(function)));
+            self
+        }
+
+        /// Attach a memory read callback to this emulator, which is given every read from RAM
+        /// along with its slice of memory, its address, and the buffer to read into.
+        /// This covers `read` instructions and fetching the targets of jumps and branches,
+        /// but not fetching instructions, or reads from devices mapped onto the [`Emulator::bus`].
+        ///
+        /// Like with [`Emulator::with_callback`], memory isn't read automatically,
+        /// and returning an interrupt code raises an [`Interrupt::Device`].
         ///
-        /// let mut emu = Emulator::<0x100000>::default()
-        ///     .with_callback(callback);
+        /// # Examples
+        /// ```rust
+        /// # use lasagna::emulator::{Emulator, Interrupt};
+        /// # use lasagna::parser::assemble;
+        /// // Reading from 0x100 gives a random-ish number, and reading from 0x200 is an error
+        /// let mut seed = 0x1234_u16;
+        /// let mut emulator = Emulator::default()
+        ///     .with_read_callback(move |mem_slice, pointer, buf| match pointer {
+        ///         0x100 => {
+        ///             seed = seed.wrapping_mul(75).wrapping_add(74);
+        ///             buf.copy_from_slice(&seed.to_be_bytes()[..buf.len()]);
+        ///             None
+        ///         },
+        ///         0x200 => Some(0xBAD),
+        ///         _ => {
+        ///             buf.copy_from_slice(mem_slice);
+        ///             None
+        ///         }
+        ///     });
+        ///
+        /// let program = assemble("literal 0x100_u32\nread u32\nmove\nread u16\nright u16").unwrap();
+        /// emulator.memory[0x20000 .. 0x20000 + program.len()].copy_from_slice(&program);
+        /// for _ in 0..4 {
+        ///     assert_eq!(emulator.step(), None);
+        /// }
+        /// assert_eq!(&emulator.val1[..2], &0x1234_u16.wrapping_mul(75).wrapping_add(74).to_be_bytes());
+        ///
+        /// emulator.ptr = 0x200;
+        /// emulator.cur = 0x20000 + 9;
+        /// assert!(matches!(emulator.step(), Some(Interrupt::Device {code: 0xBAD, ..})));
         /// ```
-        pub fn with_callback(mut self, function: fn(&mut [u8], u32, &[u8]) -> Option<u32>) -> Self {
-            self.callback = Some(function);
+        pub fn with_read_callback(mut self, function: impl FnMut(&[u8], u32, &mut [u8]) -> Option<u32> + 'static) -> Self {
+            self.read_callback = Some(Rc::new(RefCell::new(function)));
             self
         }
 
-        fn get_size(instr: u8) -> usize {
-            match instr & constants::TYPE {
-                0b000 | 0b001 | 0b111 => 1,
-                0b010 | 0b011 => 2,
-                0b100 | 0b101 | 0b110 => 4,
-                _ => unreachable!()
+        /// Turns a fault from the bus into an interrupt at the current `CUR` and `PTR`.
+        fn bus_fault(&self, fault: Fault, access: Access) -> Interrupt {
+            let (cur, ptr) = (self.cur, self.ptr);
+            match fault {
+                Fault::OutOfBounds(addr) => Interrupt::OutOfBounds {addr, access, cur, ptr},
+                Fault::Raised(code) => Interrupt::Device {code, cur, ptr}
             }
         }
 
-        fn check_size(&self, size: usize) -> bool {
-            self.ptr.checked_add(size as u32).is_none() || (self.ptr as usize + size) >= SIZE
+        /// Reads memory at `PTR` through the bus, passing reads from RAM through the read callback.
+        fn read_ptr(&mut self, buf: &mut [u8]) -> Result<(), Interrupt> {
+            self.bus.read(&self.memory, self.ptr, buf, |mem, addr, buf| match &self.read_callback {
+                Some(callback) => callback.borrow_mut()(mem, addr, buf),
+                None => {
+                    buf.copy_from_slice(mem);
+                    None
+                }
+            }).map_err(|fault| self.bus_fault(fault, Access::Read))?;
+            self.log_access(Access::Read, self.ptr, buf);
+            Ok(())
         }
 
-        #[must_use]
-        fn get_value(&mut self, instr: u8) -> Option<&mut [u8]> {
-            let cur = self.cur as usize;
+        /// Logs a memory access, if the step is being traced.
+        fn log_access(&mut self, access: Access, addr: u32, data: &[u8]) {
+            if let Some(accesses) = &mut self.accesses {
+                accesses.push(MemoryAccess {access, addr, data: data.to_vec()});
+            }
+        }
 
-            let type_size = Self::get_size(instr);
-            if self.check_size(type_size) {
-                return None;
+        /// Writes memory at `PTR` through the bus, passing writes to RAM through the callback.
+        /// The write is recorded in the journal, and seen by the trace and watchpoints,
+        /// just like one made by an instruction, so syscalls should write memory with this.
+        ///
+        /// # Errors
+        /// The write went out of bounds, or a device or the callback raised an interrupt.
+        pub fn write_ptr(&mut self, data: &[u8]) -> Result<(), Interrupt> {
+            let callback = self.callback.clone();
+            let journal = &mut self.journal;
+            self.bus.write(&mut self.memory, self.ptr, data, |mem, addr, data| {
+                if let Some(journal) = journal {
+                    journal.overwrite(addr, mem);
+                }
+                match &callback {
+                    Some(callback) => callback.borrow_mut()(mem, addr, data),
+                    None => {
+                        mem.copy_from_slice(data);
+                        None
+                    }
+                }
+            }).map_err(|fault| self.bus_fault(fault, Access::Write))?;
+            self.log_access(Access::Write, self.ptr, data);
+            Ok(())
+        }
+
+        /// Runs code on the host, like a syscall or the debugger, recording any memory it changes
+        /// in the journal by comparing memory from before and after it.
+        fn journaled<T>(&mut self, host: impl FnOnce(&mut Self) -> T) -> T {
+            let Some(before) = self.journal.is_some().then(|| self.memory.clone()) else {
+                return host(self);
+            };
+            let result = host(self);
+            if let Some(journal) = &mut self.journal {
+                let len = before.len().min(self.memory.len());
+                let changed = |addr: usize| before[addr] != self.memory[addr];
+                let mut addr = 0;
+                while addr < len {
+                    if !changed(addr) {
+                        addr += 1;
+                        continue;
+                    }
+                    let start = addr;
+                    while addr < len && changed(addr) {
+                        addr += 1;
+                    }
+                    journal.overwrite(start as u32, &before[start..addr]);
+                }
             }
-            Some(&mut self.memory[cur+1 ..= cur+type_size])
+            result
+        }
+
+        /// Records `len` bytes of memory at `addr` in the journal, before they're overwritten.
+        fn record(&mut self, addr: usize, len: usize) {
+            if let Some(journal) = &mut self.journal {
+                journal.overwrite(addr as u32, &self.memory[addr .. addr + len]);
+            }
+        }
+
+        /// Reads the target of a jump, stored as a u32 at `PTR`.
+        fn jump_target(&mut self) -> Result<u32, Interrupt> {
+            let mut target = [0; 4];
+            self.read_ptr(&mut target)?;
+            Ok(u32::from_be_bytes(target))
+        }
+
+        /// Gets the bytes of `VAL1` that are used by the given type.
+        fn get_value(&self, ty: Type) -> &[u8] {
+            &self.val1[..ty.size()]
         }
 
         /// Push a 4-byte value to the stack. If the stack is full, returns `false`.
         ///
         /// ```rust
         /// # use lasagna::emulator::Emulator;
-        /// # let mut emulator = Box::new(Emulator::<0x100000>::default());
+        /// # let mut emulator = Emulator::default();
         /// if !emulator.push([0x12, 0x34, 0x56, 0x78]) {
         ///     panic!("Stack overflowed!");
         /// }
@@ -252,20 +846,23 @@ mod structures {
         /// ```
         #[must_use]
         pub fn push(&mut self, value: [u8; 4]) -> bool {
-            let stack_length_ref: &mut [u8; 2] =
-                (&mut self.memory[0x10002 .. 0x10004]).try_into().unwrap();
-            let mut stack_length: u16 = u16::from_be_bytes(*stack_length_ref);
-            let start = 0x10004 + (stack_length * 4) as usize;
+            let stack_length = u16::from_be_bytes(self.memory[0x10002 .. 0x10004].try_into().unwrap());
+            let start = 0x10004 + (stack_length as usize * 4);
             let end = start + 4;
             if end >= 0x20000 {
                 return false;
             }
-            stack_length += 1;
+            self.record(0x10002, 2);
+            self.record(start, 4);
+            let stack_length_ref: &mut [u8; 2] =
+                (&mut self.memory[0x10002 .. 0x10004]).try_into().unwrap();
+            let stack_length = stack_length + 1;
             *stack_length_ref = stack_length.to_be_bytes();
-            drop(stack_length_ref);
             let value_ref: &mut [u8; 4] =
                 (&mut self.memory[start .. end]).try_into().unwrap();
             *value_ref = value;
+            self.log_access(Access::Write, 0x10002, &stack_length.to_be_bytes());
+            self.log_access(Access::Write, start as u32, &value);
             true
         }
 
@@ -275,7 +872,7 @@ mod structures {
         /// ```rust
         /// // Continuing from `[Emulator::push]`...
         /// # use lasagna::emulator::Emulator;
-        /// # let mut emulator = Box::new(Emulator::<0x100000>::default());
+        /// # let mut emulator = Emulator::default();
         /// # let _ = emulator.push([0x12, 0x34, 0x56, 0x78]);
         /// let value = emulator.pop().unwrap();
         /// assert_eq!(value, [0x12, 0x34, 0x56, 0x78]);
@@ -286,6 +883,7 @@ mod structures {
         /// ```
         #[must_use]
         pub fn pop(&mut self) -> Option<[u8; 4]> {
+            self.record(0x10002, 2);
             let stack_length_ref: &mut [u8; 2] =
                 (&mut self.memory[0x10002 .. 0x10004]).try_into().unwrap();
             let mut stack_length: u16 = u16::from_be_bytes(*stack_length_ref);
@@ -294,167 +892,193 @@ mod structures {
             let start = 0x10004 + (stack_length * 4) as usize;
             let end = start + 4;
             *stack_length_ref = stack_length.to_be_bytes();
-            drop(stack_length_ref);
-            Some(
-                self.memory[start .. end].try_into().unwrap()
-            )
+            let value: [u8; 4] = self.memory[start .. end].try_into().unwrap();
+            self.log_access(Access::Write, 0x10002, &stack_length.to_be_bytes());
+            self.log_access(Access::Read, start as u32, &value);
+            Some(value)
+        }
+
+        /// Raises an out of bounds interrupt at the current `CUR` and `PTR`.
+        fn out_of_bounds(&self, addr: u32, access: Access) -> StepResult {
+            Some(Interrupt::OutOfBounds {addr, access, cur: self.cur, ptr: self.ptr})
         }
 
         /// Step the emulator by 1 opcode, returning if there was an interrupt.
         ///
         /// # Implicit Interrupts
-        /// * The cursor is at the very end of the memory after execution. `Some(0)`
-        /// * The emulator tried to access out of bounds memory. `Some(1)`
-        /// * The stack was overflowed. `Some(2)`
-        /// * The stack was popped with nothing on it. `Some(3)`
-        /// * There was a divide by 0. `Some(4)`
+        /// * The cursor is at the very end of the memory after execution. [`Interrupt::Halt`]
+        /// * The emulator tried to access out of bounds memory. [`Interrupt::OutOfBounds`]
+        /// * The stack was overflowed. [`Interrupt::StackOverflow`]
+        /// * The stack was popped with nothing on it. [`Interrupt::StackUnderflow`]
+        /// * There was a divide by 0. [`Interrupt::DivideByZero`]
+        ///
+        /// Interrupts with a handler in the [`VectorTable`] are handled by the program instead.
+        ///
+        /// Moving `CUR` past the largest address is out of bounds too:
+        /// ```rust
+        /// # use lasagna::emulator::{Access, Emulator, Interrupt};
+        /// let mut emulator = Emulator::default();
+        /// emulator.memory[..4].copy_from_slice(&0xFFFFFFFF_u32.to_be_bytes());
+        /// emulator.memory[0x20000] = 0b01_000_000; // jump
+        /// assert_eq!(
+        ///     emulator.step(),
+        ///     Some(Interrupt::OutOfBounds {addr: 0xFFFFFFFF, access: Access::Execute, cur: 0xFFFFFFFF, ptr: 0})
+        /// );
+        /// ```
         #[must_use]
         pub fn step(&mut self) -> StepResult {
+            let mut journal = self.journal.take();
+            if let Some(journal) = &mut journal {
+                journal.begin(self);
+            }
+            self.journal = journal;
+
+            self.syscall_halted = false;
+            let result = match self.execute() {
+                Some(interrupt) if !self.syscall_halted => self.vector(interrupt),
+                result => result
+            };
+            if let Some(journal) = &mut self.journal {
+                journal.commit();
+            }
+            result
+        }
+
+        /// Passes an interrupt to its handler in the vector table, if there is one.
+        fn vector(&mut self, interrupt: Interrupt) -> StepResult {
+            let Some(table) = self.vector_table else {
+                return Some(interrupt);
+            };
+            let Some(index) = VectorTable::index(interrupt).filter(|index| *index < table.len as u64) else {
+                return Some(interrupt);
+            };
+            let entry = table.address as usize + index as usize * 4;
+            if entry + 4 > self.memory.len() {
+                return Some(interrupt);
+            }
+            let handler = u32::from_be_bytes(self.memory[entry .. entry + 4].try_into().unwrap());
+            if handler == 0 || !self.push(interrupt.cur().to_be_bytes()) {
+                return Some(interrupt);
+            }
+            self.cur = handler;
+            None
+        }
+
+        /// Executes a single instruction, without handling interrupts.
+        fn execute(&mut self) -> StepResult {
             let cur = self.cur as usize;
-            if !(0..SIZE).contains(&cur) {
-                return Some(1);
+            if cur >= self.memory.len() {
+                return self.out_of_bounds(self.cur, Access::Execute);
             }
-            let instr = self.memory[cur];
-            match (
-                (instr & constants::GROUP) >> 6,
-                (instr & constants::INDEX) >> 3,
-                instr & constants::TYPE
-            ) {
-                (0b100.., _, _) => unreachable!(),
-                (_, 0b1000.., _) => unreachable!(),
-                (_, _, 0b1000..) => unreachable!(),
-
-                (0b00, 0b000, _) => {},
-                (0b00, 0b001, _) => if !self.push(self.val1) {
-                    return Some(2)
+            let (instruction, length) = match Instruction::decode(&self.memory[cur..]) {
+                Some(decoded) => decoded,
+                None => return self.out_of_bounds(self.memory.len() as u32, Access::Execute)
+            };
+            let (at_cur, at_ptr) = (self.cur, self.ptr);
+            match instruction {
+                Instruction::Noop(_) => {},
+                Instruction::Push(_) => if !self.push(self.val1) {
+                    return Some(Interrupt::StackOverflow {cur: at_cur, ptr: at_ptr})
                 },
-                (0b00, 0b010, _) => {
+                Instruction::Pop(_) => {
                     match self.pop() {
                         Some(v) => self.val1 = v,
-                        None => return Some(3)
+                        None => return Some(Interrupt::StackUnderflow {cur: at_cur, ptr: at_ptr})
                     }
                 },
-                (0b00, 0b011, _) => return Some(
-                    u32::from_be_bytes(self.val1)
-                ),
-                (0b00, 0b100, 0b000) => {
-                    if self.cur.checked_add(5).is_none() {
-                        return Some(1);
-                    }
-                    self.cur += 1;
-                    let length: &[u8; 4] = (
-                        &self.memory[self.cur as usize ..= self.cur as usize + 3]
-                    ).try_into().unwrap();
-                    self.cur += 4;
-                    let length = u32::from_be_bytes(*length);
-                    if self.cur.checked_add(length).is_none() {
-                        return Some(1);
+                Instruction::Interrupt(_) if self.vector_table.is_some()
+                    && u32::from_be_bytes(self.val1) == VectorTable::RETURN => match self.pop() {
+                    Some(cur) => self.cur = u32::from_be_bytes(cur),
+                    None => return Some(Interrupt::StackUnderflow {cur: at_cur, ptr: at_ptr})
+                },
+                Instruction::Interrupt(_) => {
+                    let result = match self.syscalls.get(&u32::from_be_bytes(self.val1)).cloned() {
+                        Some(handler) => {
+                            let result = self.journaled(|emulator| handler.borrow_mut()(emulator));
+                            self.syscall_halted = result == SyscallResult::Halt;
+                            result
+                        },
+                        None => SyscallResult::Halt
+                    };
+                    match result {
+                        SyscallResult::Resume => {},
+                        SyscallResult::Halt => return Some(Interrupt::User {
+                            code: u32::from_be_bytes(self.val1), cur: at_cur, ptr: at_ptr
+                        }),
+                        SyscallResult::Raise(interrupt) => return Some(interrupt)
                     }
-                    if let Some(callback) = self.callback {
-                        let literal =
-                            self.memory[self.cur as usize .. (self.cur + length) as usize]
-                                .to_owned();
-                        callback(
-                            &mut self.memory[self.ptr as usize .. (self.ptr + length) as usize],
-                            length,
-                            &literal
-                        )?;
-                    } else {
-                        for i in 0..length {
-                            self.memory[(self.ptr + i) as usize] = self.memory[(self.cur + i) as usize];
-                        }
+                },
+                Instruction::Literal(data) => {
+                    if let Err(interrupt) = self.write_ptr(&data) {
+                        return Some(interrupt);
                     }
-                    self.cur += length;
+                    // Stop on the literal's last byte, as `CUR` is moved past it below
+                    self.cur += length as u32 - 1;
                 },
-                (0b00, 0b100, _) => self.val2 = self.val1,
-                (0b00, 0b101, _) => core::mem::swap(&mut self.val1, &mut self.val2),
-                (0b00, 0b110, ty) => {
-                    let size = Self::get_size(ty) - 1;
-                    if self.check_size(size) {return Some(1)};
-                    let mem = &self.memory[
-                        self.ptr as usize ..= self.ptr as usize + size
-                        ];
-                    // Probably a way better way to do this but :P
-                    self.val1[..(size + 1)].copy_from_slice(&mem[..(size + 1)]);
+                Instruction::Copy(_) => self.val2 = self.val1,
+                Instruction::Swap(_) => core::mem::swap(&mut self.val1, &mut self.val2),
+                Instruction::Read(ty) => {
+                    let mut value = [0; 4];
+                    if let Err(interrupt) = self.read_ptr(&mut value[..ty.size()]) {
+                        return Some(interrupt);
+                    }
+                    self.val1[..ty.size()].copy_from_slice(&value[..ty.size()]);
                 },
-                (0b00, 0b111, ty) => {
-                    let size = Self::get_size(ty) - 1;
-                    if self.check_size(size) {return Some(1)};
-                    let mem = &mut self.memory[
-                        self.ptr as usize ..= self.ptr as usize + size
-                        ];
-                    if let Some(callback) = self.callback {
-                        callback(mem, self.ptr, &self.val1)?;
-                    } else {
-                        mem[..(size + 1)].copy_from_slice(&self.val1[..(size + 1)]);
+                Instruction::Write(ty) => {
+                    let value = self.val1;
+                    if let Err(interrupt) = self.write_ptr(&value[..ty.size()]) {
+                        return Some(interrupt);
                     }
                 },
-                (0b01, 0b000, _) => {
-                    if self.check_size(3) { return Some(1); }
-                    self.cur = u32::from_be_bytes(
-                        self.memory[self.ptr as usize ..= (self.ptr + 3) as usize]
-                            .try_into().unwrap()
-                    );
+                Instruction::Jump(_) => match self.jump_target() {
+                    Ok(target) => self.cur = target,
+                    Err(interrupt) => return Some(interrupt)
                 },
-                (0b01, 0b001, ty) => {
-                    if self.check_size(3) { return Some(1); }
-                    let jump = match self.get_value(ty) {
-                        Some(v) => v,
-                        None => return Some(1)
-                    };
-                    if jump.iter().all(|v| *v == 0) {
-                        self.cur = u32::from_be_bytes(
-                            self.memory[self.ptr as usize ..= (self.ptr + 3) as usize]
-                                .try_into().unwrap()
-                        );
+                Instruction::Branch(ty) => if self.get_value(ty).iter().all(|v| *v == 0) {
+                    match self.jump_target() {
+                        Ok(target) => self.cur = target,
+                        Err(interrupt) => return Some(interrupt)
                     }
                 },
-                (0b01, 0b010, ty) => {
-                    if self.check_size(3) { return Some(1); }
-                    let jump = match self.get_value(ty) {
-                        Some(v) => v,
-                        None => return Some(1)
-                    };
-                    if jump.iter().any(|v| *v != 0) {
-                        self.cur = u32::from_be_bytes(
-                            self.memory[self.ptr as usize ..= (self.ptr + 3) as usize]
-                                .try_into().unwrap()
-                        );
+                Instruction::BranchZero(ty) => if self.get_value(ty).iter().any(|v| *v != 0) {
+                    match self.jump_target() {
+                        Ok(target) => self.cur = target,
+                        Err(interrupt) => return Some(interrupt)
                     }
                 },
-                (0b01, 0b011, _) => {
+                Instruction::Goto(_) => {
                     self.cur = match self.ptr.checked_add(1) {
                         Some(v) => v,
-                        None => return Some(1)
+                        None => return self.out_of_bounds(self.ptr, Access::Pointer)
                     };
                 },
-                (0b01, 0b100, ty) => {
-                    let size = Self::get_size(ty);
-                    self.ptr = match self.ptr.checked_add(size as u32) {
+                Instruction::Left(ty) => {
+                    let size = ty.size();
+                    self.ptr = match self.ptr.checked_sub(size as u32) {
                         Some(v) => v,
-                        None => return Some(1)
+                        None => return self.out_of_bounds(self.ptr, Access::Pointer)
                     };
                 },
-                (0b01, 0b101, ty) => {
-                    let size = Self::get_size(ty);
-                    self.ptr = match self.ptr.checked_sub(size as u32) {
+                Instruction::Right(ty) => {
+                    let size = ty.size();
+                    self.ptr = match self.ptr.checked_add(size as u32) {
                         Some(v) => v,
-                        None => return Some(1)
+                        None => return self.out_of_bounds(self.ptr, Access::Pointer)
                     };
                 },
-                (0b01, 0b110, _) => self.ptr = u32::from_be_bytes(self.val1),
-                (0b01, 0b111, _) => self.val1 = self.ptr.to_be_bytes(),
-                (0b10, 0b000, ty) => {overflowing!(
+                Instruction::Move(_) => self.ptr = u32::from_be_bytes(self.val1),
+                Instruction::Pointer(_) => self.val1 = self.ptr.to_be_bytes(),
+                Instruction::Add(ty) => {overflowing!(
                     self, ty, overflowing_add, +,
-                    |a: u8, b: u8| (a ^ b),
+                    |a: u8, b: u8| a ^ b,
                     |a: u8, b: u8| (a & b) != 0
                 );},
-                (0b10, 0b001, ty) => {overflowing!(
+                Instruction::Subtract(ty) => {overflowing!(
                     self, ty, overflowing_sub, -,
-                    |a: u8, b: u8| (!a & b),
+                    |a: u8, b: u8| !a & b,
                     |a: u8, b: u8| (!b & a) != 0
                 );},
-                (0b10, 0b010, ty) => {
+                Instruction::Multiply(ty) => {
                     let lhs = Value::from_bytes(&self.val1, ty);
                     let rhs = Value::from_bytes(&self.val2, ty);
                     use Value::*;
@@ -474,19 +1098,19 @@ mod structures {
                     let (over, size) = over.into_bytes();
                     self.val2[..size].copy_from_slice(&over[..size]);
                 },
-                (0b10, 0b011, ty) => {
+                Instruction::Divide(ty) => {
                     let lhs = Value::from_bytes(&self.val1, ty);
                     let rhs = Value::from_bytes(&self.val2, ty);
                     use Value::*;
                     let (val, over) = match (lhs, rhs) {
-                        (U8(a), U8(b)) => {if b == 0 {return Some(4)}; (U8(a / b), U8(a % b))},
-                        (I8(a), I8(b)) => {if b == 0 {return Some(4)}; (I8(a.wrapping_div(b)), I8(a.wrapping_rem(b)))},
-                        (U16(a), U16(b)) => {if b == 0 {return Some(4)}; (U16(a / b), U16(a % b))},
-                        (I16(a), I16(b)) => {if b == 0 {return Some(4)}; (I16(a.wrapping_div(b)), I16(a.wrapping_rem(b)))},
-                        (U32(a), U32(b)) => {if b == 0 {return Some(4)}; (U32(a / b), U32(a % b))},
-                        (I32(a), I32(b)) => {if b == 0 {return Some(4)}; (I32(a.wrapping_div(b)), I32(a.wrapping_rem(b)))},
-                        (Float(a), Float(b)) => {if b == 0.0 {return Some(4)}; (Float((a / b).trunc() * b), Float(a % b))},
-                        (Bool(_), Bool(b)) => {if b == 0 {return Some(4)}; (lhs, Bool(0))},
+                        (U8(a), U8(b)) => {if b == 0 {return Some(Interrupt::DivideByZero {cur: at_cur, ptr: at_ptr})}; (U8(a / b), U8(a % b))},
+                        (I8(a), I8(b)) => {if b == 0 {return Some(Interrupt::DivideByZero {cur: at_cur, ptr: at_ptr})}; (I8(a.wrapping_div(b)), I8(a.wrapping_rem(b)))},
+                        (U16(a), U16(b)) => {if b == 0 {return Some(Interrupt::DivideByZero {cur: at_cur, ptr: at_ptr})}; (U16(a / b), U16(a % b))},
+                        (I16(a), I16(b)) => {if b == 0 {return Some(Interrupt::DivideByZero {cur: at_cur, ptr: at_ptr})}; (I16(a.wrapping_div(b)), I16(a.wrapping_rem(b)))},
+                        (U32(a), U32(b)) => {if b == 0 {return Some(Interrupt::DivideByZero {cur: at_cur, ptr: at_ptr})}; (U32(a / b), U32(a % b))},
+                        (I32(a), I32(b)) => {if b == 0 {return Some(Interrupt::DivideByZero {cur: at_cur, ptr: at_ptr})}; (I32(a.wrapping_div(b)), I32(a.wrapping_rem(b)))},
+                        (Float(a), Float(b)) => {if b == 0.0 {return Some(Interrupt::DivideByZero {cur: at_cur, ptr: at_ptr})}; (Float((a / b).trunc() * b), Float(a % b))},
+                        (Bool(_), Bool(b)) => {if b == 0 {return Some(Interrupt::DivideByZero {cur: at_cur, ptr: at_ptr})}; (lhs, Bool(0))},
                         _ => unreachable!()
                     };
                     let (val, size) = val.into_bytes();
@@ -494,7 +1118,7 @@ mod structures {
                     let (over, size) = over.into_bytes();
                     self.val2[..size].copy_from_slice(&over[..size]);
                 },
-                (0b10, 0b100, ty) => {
+                Instruction::Compare(ty) => {
                     let lhs = Value::from_bytes(&self.val1, ty);
                     let rhs = Value::from_bytes(&self.val2, ty);
                     self.val1[0] = match lhs.partial_cmp(&rhs) {
@@ -502,61 +1126,64 @@ mod structures {
                         None => 0x7F
                     };
                 },
-                (0b10, 0b101, ty) => {
-                    let size = Self::get_size(ty);
+                Instruction::And(ty) => {
+                    let size = ty.size();
                     for i in 0..size {
                         self.val1[i] &= self.val2[i];
                     }
                 },
-                (0b10, 0b110, ty) => {
-                    let size = Self::get_size(ty);
+                Instruction::Or(ty) => {
+                    let size = ty.size();
                     for i in 0..size {
                         self.val1[i] |= self.val2[i];
                     }
                 },
-                (0b10, 0b111, ty) => {
-                    let size = Self::get_size(ty);
+                Instruction::Not(ty) => {
+                    let size = ty.size();
                     for i in 0..size {
                         self.val1[i] = !self.val1[i];
                     }
                 },
-                (0b11, 0b000, 0b000) => {
+                Instruction::ShiftLeft => {
                     let amount = self.val2[0] % 32;
                     let val = u32::from_be_bytes(self.val1) << amount;
                     self.val1 = val.to_be_bytes();
                 },
-                (0b11, 0b001, 0b001) => {
+                Instruction::ShiftRight => {
                     let amount = self.val2[0] % 32;
                     let val = u32::from_be_bytes(self.val1) >> amount;
                     self.val1 = val.to_be_bytes();
                 },
-                (0b11, 0b010, 0b010) => {
+                Instruction::RotateLeft => {
                     let amount = self.val2[0];
                     let val = u32::from_be_bytes(self.val1).rotate_left(amount as u32);
                     self.val1 = val.to_be_bytes();
                 },
-                (0b11, 0b011, 0b011) => {
+                Instruction::RotateRight => {
                     let amount = self.val2[0] % 32;
                     let val = u32::from_be_bytes(self.val1).rotate_right(amount as u32);
                     self.val1 = val.to_be_bytes();
                 },
-                (0b11, 0b100, 0b100) => self.val1[0] ^= self.val2[0],
-                (0b11, 0b101, 0b101) => {
+                Instruction::Xor(Type::U8 | Type::I8 | Type::Bool) => self.val1[0] ^= self.val2[0],
+                Instruction::Xor(Type::U16 | Type::I16) => {
                     self.val1[0] ^= self.val2[0];
                     self.val1[1] ^= self.val2[1];
                 },
-                (0b11, 0b110, 0b110) => {
+                Instruction::Xor(Type::U32 | Type::I32 | Type::Float) => {
                     // eh screw it
                     self.val1[0] ^= self.val2[0];
                     self.val1[1] ^= self.val2[1];
                     self.val1[2] ^= self.val2[2];
                     self.val1[3] ^= self.val2[3];
                 },
-                (0b11, 0b111, 0b111) => if let Some(debugger) = self.debugger {
-                    debugger(self)?;
+                Instruction::Break => if let Some(debugger) = self.debugger.clone() {
+                    if let Some(code) = self.journaled(|emulator| debugger.borrow_mut()(emulator)) {
+                        return Some(Interrupt::Debugger {code, cur: at_cur, ptr: at_ptr});
+                    }
                 },
-                (0b11, from, to) => {
+                Instruction::Cast {from, to} => {
                     let old = Value::from_bytes(&self.val1, from);
+                    let to = to.bits();
                     const FLOAT_ONE: [u8; 4] = [0x3F, 0x80, 0x00, 0x00];
                     match (old, to) {
                         // Unreachables
@@ -621,16 +1248,21 @@ mod structures {
                     }
                 }
             }
-            if SIZE - 1 == cur {
-                Some(0)
+            if self.memory.len() - 1 == cur {
+                Some(Interrupt::Halt {cur: self.cur, ptr: self.ptr})
             } else {
-                self.cur += 1;
-                None
+                match self.cur.checked_add(1) {
+                    Some(next) => {
+                        self.cur = next;
+                        None
+                    },
+                    None => self.out_of_bounds(self.cur, Access::Execute)
+                }
             }
         }
     }
 
-    impl<const SIZE: usize> Iterator for Emulator<SIZE> {
+    impl Iterator for Emulator {
         type Item = StepResult;
 
         fn next(&mut self) -> Option<StepResult> {
@@ -639,4 +1271,4 @@ mod structures {
     }
 }
 
-pub use structures::Emulator;
\ No newline at end of file
+pub use structures::{Emulator, EmulatorBuilder, BuildError, Callback, ReadCallback, Debugger, Syscall, SyscallResult, VectorTable, Interrupt, Access, StepResult};
\ No newline at end of file
@@ -0,0 +1,364 @@
+//! Executables, which hold a program along with how to load and start it.
+//!
+//! Unlike a raw program, which is just bytes to load at `0x20000`, an executable says
+//! where its segments go, what `CUR` and `PTR` start at, how much memory it needs,
+//! and which revision of the instruction set it's for. It can also hold the program's symbols
+//! and any other debugging information. Executables are usually given the extension `.lsgx`.
+//!
+//! # Format
+//! Everything is big-endian.
+//!
+//! | Size  | Description                                          |
+//! |:-----:|:-----------------------------------------------------|
+//! | `4`   | The magic bytes `LSGX`.                              |
+//! | `2`   | The format version, currently `1`.                   |
+//! | `2`   | The revision of the instruction set, currently `1`.  |
+//! | `4`   | The address of the first instruction, which `CUR` starts at. |
+//! | `4`   | What `PTR` starts at.                                |
+//! | `4`   | The least amount of memory needed, at most [`MAX_MEMORY_SIZE`]. |
+//! | `4`   | The number of sections.                              |
+//! | ...   | Each section, as below.                              |
+//!
+//! Each section starts with its kind as a u16, two reserved null bytes,
+//! its address and the length of its data as u32s, and then its data.
+//!
+//! | Kind  | Description                                          |
+//! |:-----:|:-----------------------------------------------------|
+//! | `1`   | A segment, loaded into memory at its address.        |
+//! | `2`   | The program's symbols, as a [map file](crate::symbols). Its address is `0`. |
+//! | `3`   | Debugging information, as the length of its name as a u16, its name in UTF-8, and then anything. Its address is `0`. |
+//!
+//! Sections of other kinds are skipped, so that they can be added without breaking older loaders.
+//! Segments must be in order, and can't overlap or go past the end of memory.
+//!
+//! ```rust
+//! # use lasagna::executable::Executable;
+//! # use lasagna::parser::assemble_with_symbols;
+//! let (program, symbols) = assemble_with_symbols("
+//!     literal 0_u32
+//!     label end
+//!     interrupt
+//! ").unwrap();
+//! let mut executable = Executable::from_program(&program).unwrap();
+//! executable.symbols = Some(symbols);
+//!
+//! let bytes = executable.to_bytes();
+//! let loaded = Executable::from_bytes(&bytes).unwrap();
+//! assert_eq!(loaded, executable);
+//! assert_eq!(loaded.memory_size, 0x30000);
+//!
+//! let mut emulator = loaded.emulator().unwrap();
+//! assert_eq!(emulator.memory.len(), 0x100000);
+//! assert_eq!(&emulator.memory[0x20000 .. 0x20000 + program.len()], &program);
+//! assert!(emulator.find_map(|result| result).is_some());
+//! assert_eq!(emulator.cur, loaded.symbols.unwrap().address("end").unwrap());
+//! ```
+use std::fmt;
+
+use crate::constants;
+use crate::emulator::Emulator;
+use crate::reader::{Reader, Truncated};
+use crate::symbols::SymbolMap;
+
+/// The magic bytes an executable starts with.
+pub const MAGIC: [u8; 4] = *b"LSGX";
+
+/// The version of the format that executables are written in.
+pub const VERSION: u16 = 1;
+
+/// The revision of the instruction set that this crate implements.
+pub const ISA_REVISION: u16 = 1;
+
+/// The most memory an executable can ask for.
+/// Emulators given more memory than this up front can still load executables into all of it.
+pub use crate::constants::MAX_MEMORY_SIZE;
+
+/// The kinds of sections.
+const SEGMENT: u16 = 1;
+const SYMBOLS: u16 = 2;
+const DEBUG: u16 = 3;
+
+/// An error raised when an executable can't be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ExecutableError {
+    /// The data doesn't start with [`MAGIC`].
+    BadMagic,
+    /// The executable is in a version of the format that isn't supported, which is given.
+    UnsupportedVersion(u16),
+    /// The executable is for a revision of the instruction set that isn't supported, which is given.
+    UnsupportedIsa(u16),
+    /// The data ended before the executable did.
+    Truncated,
+    /// There's more data after the end of the executable.
+    TrailingData,
+    /// The size of memory is too small to hold the stack, with the given size.
+    MemoryTooSmall(u32),
+    /// The size of memory is larger than [`MAX_MEMORY_SIZE`], with the given size.
+    MemoryTooLarge(u32),
+    /// A segment at the given address overlaps with the previous segment, or goes past the end of memory.
+    InvalidSegment(u32),
+    /// The symbols aren't a valid map file, with the line of the error if there is one.
+    InvalidSymbols(Option<usize>),
+    /// A section of debugging information has a name that isn't valid UTF-8.
+    InvalidDebugName
+}
+
+impl fmt::Display for ExecutableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic => f.write_str("not an executable"),
+            Self::UnsupportedVersion(version) => write!(f, "unsupported executable version {version}"),
+            Self::UnsupportedIsa(revision) => write!(f, "unsupported instruction set revision {revision}"),
+            Self::Truncated => f.write_str("executable is truncated"),
+            Self::TrailingData => f.write_str("unexpected data after the end of the executable"),
+            Self::MemoryTooSmall(size) =>
+                write!(f, "memory of {size:#X} bytes is too small to contain the stack"),
+            Self::MemoryTooLarge(size) =>
+                write!(f, "memory of {size:#X} bytes is larger than the limit of {MAX_MEMORY_SIZE:#X}"),
+            Self::InvalidSegment(address) => write!(f, "invalid segment at {address:#010X}"),
+            Self::InvalidSymbols(Some(line)) => write!(f, "invalid symbol on line {line}"),
+            Self::InvalidSymbols(None) => f.write_str("symbols aren't valid UTF-8"),
+            Self::InvalidDebugName => f.write_str("debugging information has an invalid name")
+        }
+    }
+}
+
+impl std::error::Error for ExecutableError {}
+
+impl From<Truncated> for ExecutableError {
+    fn from(_: Truncated) -> Self {
+        Self::Truncated
+    }
+}
+
+/// A segment of an executable, loaded into memory at its address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub address: u32,
+    pub data: Vec<u8>
+}
+
+/// A program, along with how to load and start it. See the [module documentation](self).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Executable {
+    /// The address of the first instruction, which `CUR` starts at.
+    pub entry: u32,
+    /// What `PTR` starts at.
+    pub ptr: u32,
+    /// The least amount of memory needed, which must be more than `0x20000`,
+    /// and at most [`MAX_MEMORY_SIZE`].
+    pub memory_size: u32,
+    /// The segments to load, in order of address.
+    pub segments: Vec<Segment>,
+    pub symbols: Option<SymbolMap>,
+    /// Any other debugging information, by name.
+    pub debug: Vec<(String, Vec<u8>)>
+}
+
+impl Executable {
+    /// Wraps a raw program, loaded and started at `0x20000`.
+    ///
+    /// # Errors
+    /// The program is too large for the memory it needs to fit in [`MAX_MEMORY_SIZE`].
+    pub fn from_program(program: &[u8]) -> Result<Self, ExecutableError> {
+        let segment = Segment {address: constants::PROGRAM_START, data: program.to_vec()};
+        Self::from_segments(vec![segment], constants::PROGRAM_START)
+    }
+
+    /// Creates an executable from segments in order of address, starting at `entry`.
+    /// The memory needed is the end of the last segment rounded up to a multiple of 64 KiB,
+    /// and is at least enough for the stack.
+    ///
+    /// # Errors
+    /// The memory needed is larger than [`MAX_MEMORY_SIZE`].
+    ///
+    /// ```rust
+    /// # use lasagna::executable::{Executable, ExecutableError, Segment};
+    /// let segment = |address, length| Segment {address, data: vec![0; length]};
+    /// assert_eq!(Executable::from_segments(vec![segment(0x20000, 0x10000)], 0x20000).unwrap().memory_size, 0x30000);
+    /// assert_eq!(Executable::from_segments(vec![segment(0x20000, 0x10001)], 0x20000).unwrap().memory_size, 0x40000);
+    /// assert_eq!(
+    ///     Executable::from_segments(vec![segment(0x1000_0000, 1)], 0x20000),
+    ///     Err(ExecutableError::MemoryTooLarge(0x1001_0000))
+    /// );
+    /// ```
+    pub fn from_segments(segments: Vec<Segment>, entry: u32) -> Result<Self, ExecutableError> {
+        // Memory has to be larger than `0x20000` to hold the stack
+        let end = segments.iter()
+            .map(|segment| segment.address as u64 + segment.data.len() as u64)
+            .fold(constants::PROGRAM_START as u64 + 1, u64::max);
+        let memory_size = (end + 0xFFFF) & !0xFFFF;
+        if memory_size > MAX_MEMORY_SIZE as u64 {
+            return Err(ExecutableError::MemoryTooLarge(memory_size.min(u32::MAX as u64) as u32));
+        }
+        let memory_size = memory_size as u32;
+        Ok(Self {entry, ptr: 0, memory_size, segments, symbols: None, debug: Vec::new()})
+    }
+
+    /// Writes the executable out in the binary format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&VERSION.to_be_bytes());
+        bytes.extend_from_slice(&ISA_REVISION.to_be_bytes());
+        let count = self.segments.len() + self.symbols.is_some() as usize + self.debug.len();
+        for value in [self.entry, self.ptr, self.memory_size, count as u32] {
+            bytes.extend_from_slice(&value.to_be_bytes());
+        }
+
+        let mut section = |kind: u16, address: u32, data: &[u8]| {
+            bytes.extend_from_slice(&kind.to_be_bytes());
+            bytes.extend_from_slice(&[0; 2]);
+            bytes.extend_from_slice(&address.to_be_bytes());
+            bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(data);
+        };
+        for segment in &self.segments {
+            section(SEGMENT, segment.address, &segment.data);
+        }
+        if let Some(symbols) = &self.symbols {
+            section(SYMBOLS, 0, symbols.to_string().as_bytes());
+        }
+        for (name, data) in &self.debug {
+            let mut contents = (name.len() as u16).to_be_bytes().to_vec();
+            contents.extend_from_slice(name.as_bytes());
+            contents.extend_from_slice(data);
+            section(DEBUG, 0, &contents);
+        }
+        bytes
+    }
+
+    /// Reads an executable in the binary format.
+    ///
+    /// # Errors
+    /// * The data isn't an executable, or is in a different version of the format
+    ///   or for a different revision of the instruction set.
+    /// * The data is cut short, or goes on past the end of the executable.
+    /// * The memory is too small or too large, or the segments don't fit into it in order.
+    /// * The symbols or debugging information are invalid.
+    ///
+    /// ```rust
+    /// # use lasagna::executable::{Executable, ExecutableError};
+    /// assert_eq!(Executable::from_bytes(b"LSGS"), Err(ExecutableError::BadMagic));
+    /// assert_eq!(Executable::from_bytes(b"LSGX\0\x01\0\x02"), Err(ExecutableError::UnsupportedIsa(2)));
+    ///
+    /// let huge = b"LSGX\0\x01\0\x01\0\x02\0\0\0\0\0\0\xFF\xFF\xFF\xFF\0\0\0\0";
+    /// assert_eq!(Executable::from_bytes(huge), Err(ExecutableError::MemoryTooLarge(0xFFFFFFFF)));
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ExecutableError> {
+        let mut reader = Reader(bytes);
+        if reader.take(4).map_err(|_| ExecutableError::BadMagic)? != MAGIC {
+            return Err(ExecutableError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(ExecutableError::UnsupportedVersion(version));
+        }
+        let revision = reader.u16()?;
+        if revision != ISA_REVISION {
+            return Err(ExecutableError::UnsupportedIsa(revision));
+        }
+        let entry = reader.u32()?;
+        let ptr = reader.u32()?;
+        let memory_size = reader.u32()?;
+        if memory_size <= 0x20000 {
+            return Err(ExecutableError::MemoryTooSmall(memory_size));
+        }
+        if memory_size > MAX_MEMORY_SIZE {
+            return Err(ExecutableError::MemoryTooLarge(memory_size));
+        }
+
+        let count = reader.u32()?;
+        let mut executable = Self {entry, ptr, memory_size, segments: Vec::new(), symbols: None, debug: Vec::new()};
+        let mut end = 0;
+        for _ in 0..count {
+            let kind = reader.u16()?;
+            reader.take(2)?;
+            let address = reader.u32()?;
+            let length = reader.u32()?;
+            let data = reader.take(length as usize)?;
+            match kind {
+                SEGMENT => {
+                    if (address as u64) < end || address as u64 + length as u64 > memory_size as u64 {
+                        return Err(ExecutableError::InvalidSegment(address));
+                    }
+                    end = address as u64 + length as u64;
+                    executable.segments.push(Segment {address, data: data.to_vec()});
+                },
+                SYMBOLS => {
+                    let text = std::str::from_utf8(data).map_err(|_| ExecutableError::InvalidSymbols(None))?;
+                    let symbols = SymbolMap::parse(text).map_err(|err| ExecutableError::InvalidSymbols(Some(err.0)))?;
+                    executable.symbols = Some(symbols);
+                },
+                DEBUG => {
+                    let mut section = Reader(data);
+                    let length = section.u16()?;
+                    let name = std::str::from_utf8(section.take(length as usize)?)
+                        .map_err(|_| ExecutableError::InvalidDebugName)?;
+                    executable.debug.push((name.to_string(), section.0.to_vec()));
+                },
+                _ => ()
+            }
+        }
+        if !reader.0.is_empty() {
+            return Err(ExecutableError::TrailingData);
+        }
+        Ok(executable)
+    }
+
+    /// Loads the executable into an emulator, growing its memory if it's too small,
+    /// and setting `CUR` and `PTR`. Anything attached to the emulator is kept, but its journal is cleared.
+    ///
+    /// # Errors
+    /// The memory needed is larger than [`MAX_MEMORY_SIZE`], or a segment goes past the end of memory,
+    /// in which case the emulator is left as it was.
+    ///
+    /// ```rust
+    /// # use lasagna::emulator::Emulator;
+    /// # use lasagna::executable::{Executable, ExecutableError, Segment};
+    /// let mut executable = Executable::from_program(&[0xFF]).unwrap();
+    /// executable.segments.push(Segment {address: 0x2FFFF, data: vec![0; 2]});
+    /// assert_eq!(executable.load(&mut Emulator::default()), Ok(()));
+    /// executable.segments.push(Segment {address: 0xFFFFF, data: vec![0; 2]});
+    /// assert_eq!(executable.load(&mut Emulator::default()), Err(ExecutableError::InvalidSegment(0xFFFFF)));
+    /// ```
+    pub fn load(&self, emulator: &mut Emulator) -> Result<(), ExecutableError> {
+        if self.memory_size > MAX_MEMORY_SIZE {
+            return Err(ExecutableError::MemoryTooLarge(self.memory_size));
+        }
+        let size = emulator.memory.len().max(self.memory_size as usize);
+        for segment in &self.segments {
+            if segment.address as usize + segment.data.len() > size {
+                return Err(ExecutableError::InvalidSegment(segment.address));
+            }
+        }
+
+        if let Some(journal) = &mut emulator.journal {
+            journal.clear();
+        }
+        if emulator.memory.len() < self.memory_size as usize {
+            let mut memory = vec![0; self.memory_size as usize];
+            memory[..emulator.memory.len()].copy_from_slice(&emulator.memory);
+            emulator.memory = memory.into_boxed_slice();
+        }
+        for segment in &self.segments {
+            let address = segment.address as usize;
+            emulator.memory[address .. address + segment.data.len()].copy_from_slice(&segment.data);
+        }
+        emulator.cur = self.entry;
+        emulator.ptr = self.ptr;
+        Ok(())
+    }
+
+    /// Creates an emulator with the executable loaded into it,
+    /// with the default amount of memory if that's enough.
+    ///
+    /// # Errors
+    /// The executable couldn't be loaded. See [`Executable::load`].
+    pub fn emulator(&self) -> Result<Emulator, ExecutableError> {
+        let mut emulator = Emulator::default();
+        self.load(&mut emulator)?;
+        Ok(emulator)
+    }
+}
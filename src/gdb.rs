@@ -0,0 +1,493 @@
+//! A stub for the GDB Remote Serial Protocol, so that programs can be debugged from GDB.
+//!
+//! A [`GdbStub`] drives an emulator on behalf of a client like GDB, over any pair of streams,
+//! like a TCP socket from [`serve_tcp`] or a pipe through stdin and stdout.
+//! It supports reading and writing registers and memory, stepping, continuing,
+//! software breakpoints, hardware breakpoints and watchpoints.
+//!
+//! The registers are `val1`, `val2`, `ptr`, `cur` and `stat`, in that order, all as big-endian u32s,
+//! and are described to the client through `target.xml`.
+//! Software breakpoints are inserted by overwriting the instruction with the `break` opcode,
+//! which reads of memory through the stub hide. Hardware breakpoints and watchpoints
+//! use the emulator's own [breakpoints](crate::breakpoints).
+//!
+//! Interrupts halting the emulator are reported to the client as the program exiting,
+//! with user interrupts exiting with the lowest byte of their code.
+//! Faults are reported as signals: `SIGSEGV` for going out of bounds or overflowing the stack,
+//! and `SIGFPE` for dividing by zero.
+//!
+//! The stub only reads from the client while the emulator is stopped,
+//! so a client can't interrupt a program that runs forever.
+//!
+//! ```rust
+//! # use lasagna::emulator::Emulator;
+//! # use lasagna::gdb::GdbStub;
+//! # use lasagna::parser::assemble;
+//! fn packet(data: &str) -> String {
+//!     let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+//!     format!("${data}#{checksum:02x}")
+//! }
+//!
+//! let program = assemble("
+//!     literal 0_u16
+//!     read u16
+//!     copy
+//!     interrupt
+//! ").unwrap();
+//! let mut emulator = Emulator::default();
+//! emulator.memory[0x20000 .. 0x20000 + program.len()].copy_from_slice(&program);
+//!
+//! let requests = ["QStartNoAckMode", "Z0,20007,1", "c", "p3", "m20007,1", "s", "p3", "c", "k"];
+//! let input: String = requests.into_iter().map(packet).collect();
+//! let mut output = Vec::new();
+//! GdbStub::new(emulator, input.as_bytes(), &mut output).serve().unwrap();
+//!
+//! let output = String::from_utf8(output).unwrap();
+//! let replies: Vec<&str> = output.split('$').skip(1).map(|reply| &reply[..reply.len() - 3]).collect();
+//! assert_eq!(replies, ["OK", "OK", "S05", "00020007", "32", "S05", "00020008", "W00"]);
+//! ```
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+
+use crate::breakpoints::{Register, StopReason, WatchKind};
+use crate::emulator::{Emulator, Interrupt};
+
+/// The opcode of `break`, which software breakpoints are inserted as.
+const BREAK: u8 = 0xFF;
+
+/// The description of the registers sent to the client.
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.lasagna.core">
+    <reg name="val1" bitsize="32" type="uint32" regnum="0"/>
+    <reg name="val2" bitsize="32" type="uint32"/>
+    <reg name="ptr" bitsize="32" type="data_ptr"/>
+    <reg name="cur" bitsize="32" type="code_ptr"/>
+    <reg name="stat" bitsize="32" type="uint32"/>
+  </feature>
+</target>
+"#;
+
+/// A GDB Remote Serial Protocol server for an emulator. See the [module documentation](self).
+pub struct GdbStub<R: Read, W: Write> {
+    emulator: Emulator,
+    input: R,
+    output: W,
+    /// Whether the client has turned off acknowledgements.
+    no_ack: bool,
+    /// The last packet sent, in case the client asks for it again.
+    last: Vec<u8>,
+    /// The addresses of the software breakpoints, with the bytes they overwrote.
+    software: HashMap<u32, u8>,
+    /// The IDs of the watchpoints, by their type, address and length.
+    watchpoints: HashMap<(u8, u32, u32), usize>
+}
+
+impl<R: Read, W: Write> GdbStub<R, W> {
+    /// Creates a stub which reads packets from `input`, and writes replies to `output`.
+    pub fn new(emulator: Emulator, input: R, output: W) -> Self {
+        Self {
+            emulator,
+            input,
+            output,
+            no_ack: false,
+            last: Vec::new(),
+            software: HashMap::new(),
+            watchpoints: HashMap::new()
+        }
+    }
+
+    /// The emulator being debugged.
+    /// Any software breakpoints are still inserted into its memory.
+    pub fn emulator(&mut self) -> &mut Emulator {
+        &mut self.emulator
+    }
+
+    /// Serves packets until the client kills the program, detaches or disconnects.
+    /// Software breakpoints are removed from memory at the end.
+    ///
+    /// # Errors
+    /// Reading from or writing to the client failed.
+    ///
+    /// ```rust
+    /// # use lasagna::emulator::Emulator;
+    /// # use lasagna::gdb::GdbStub;
+    /// // Empty packets, like anything else unsupported, get an empty reply
+    /// let mut output = Vec::new();
+    /// GdbStub::new(Emulator::default(), &b"$#00$\xFF#ff"[..], &mut output).serve().unwrap();
+    /// assert_eq!(output, b"+$#00+$#00");
+    ///
+    /// // Writing memory with a bad payload changes none of it
+    /// let mut output = Vec::new();
+    /// let mut stub = GdbStub::new(Emulator::default(), &b"$M0,2:12zz#6c"[..], &mut output);
+    /// stub.serve().unwrap();
+    /// assert_eq!(&stub.emulator().memory[..2], &[0, 0]);
+    /// assert_eq!(output, b"+$E01#a6");
+    /// ```
+    pub fn serve(&mut self) -> io::Result<()> {
+        let result = self.serve_packets();
+        for (addr, original) in self.software.drain() {
+            self.emulator.memory[addr as usize] = original;
+        }
+        result
+    }
+
+    /// Gives back the emulator, with any software breakpoints still inserted into its memory.
+    pub fn into_emulator(self) -> Emulator {
+        self.emulator
+    }
+
+    fn serve_packets(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.receive()? {
+            let packet = String::from_utf8_lossy(&packet).into_owned();
+            let reply = match packet.as_bytes().first() {
+                Some(b'k') => return Ok(()),
+                Some(b'D') => {
+                    self.send(b"OK")?;
+                    return Ok(());
+                },
+                _ => self.handle(&packet)
+            };
+            self.send(reply.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Reads the next packet, acknowledging it unless acknowledgements are off.
+    /// Returns `None` once the client disconnects.
+    fn receive(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            let Some(byte) = self.read_byte()? else {
+                return Ok(None);
+            };
+            match byte {
+                b'$' => (),
+                b'-' if !self.no_ack => {
+                    let last = self.last.clone();
+                    self.output.write_all(&last)?;
+                    self.output.flush()?;
+                    continue;
+                },
+                // Acknowledgements, and anything else between packets, like interrupt requests
+                _ => continue
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                    None => return Ok(None)
+                }
+            }
+            let (Some(high), Some(low)) = (self.read_byte()?, self.read_byte()?) else {
+                return Ok(None);
+            };
+            let checksum = std::str::from_utf8(&[high, low]).ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+            if self.no_ack {
+                return Ok(Some(data));
+            }
+            if checksum == Some(checksum_of(&data)) {
+                self.output.write_all(b"+")?;
+                return Ok(Some(data));
+            }
+            self.output.write_all(b"-")?;
+            self.output.flush()?;
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        loop {
+            match self.input.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err)
+            }
+        }
+    }
+
+    /// Sends a packet, escaping its data.
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut escaped = Vec::with_capacity(data.len());
+        for &byte in data {
+            if matches!(byte, b'#' | b'$' | b'}' | b'*') {
+                escaped.extend_from_slice(&[b'}', byte ^ 0x20]);
+            } else {
+                escaped.push(byte);
+            }
+        }
+        let mut packet = vec![b'$'];
+        packet.extend_from_slice(&escaped);
+        packet.extend_from_slice(format!("#{:02x}", checksum_of(&escaped)).as_bytes());
+        self.output.write_all(&packet)?;
+        self.output.flush()?;
+        self.last = packet;
+        Ok(())
+    }
+
+    /// Handles a packet, returning the reply. Unsupported packets get an empty reply.
+    fn handle(&mut self, packet: &str) -> String {
+        // Empty packets, and ones starting with a character that isn't ASCII, aren't commands
+        let Some(command) = packet.get(..1) else {
+            return String::new();
+        };
+        let args = &packet[1..];
+        let reply = match command {
+            "?" => Some("S05".into()),
+            "g" => Some(Register::ALL.iter().map(|register| format!("{:08x}", register.get(&self.emulator))).collect()),
+            "G" => self.write_registers(args),
+            "p" => parse_hex(args)
+                .and_then(|index| Register::ALL.get(index as usize))
+                .map(|register| format!("{:08x}", register.get(&self.emulator))),
+            "P" => self.write_register(args),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "s" => {
+                if let Some(addr) = args.split(';').next().and_then(parse_hex) {
+                    self.emulator.cur = addr;
+                }
+                Some(self.step())
+            },
+            "c" => {
+                if let Some(addr) = args.split(';').next().and_then(parse_hex) {
+                    self.emulator.cur = addr;
+                }
+                Some(self.resume())
+            },
+            "Z" => self.set_breakpoint(args, true),
+            "z" => self.set_breakpoint(args, false),
+            "H" => Some("OK".into()),
+            "q" | "Q" => self.query(packet),
+            _ => None
+        };
+        reply.unwrap_or_default()
+    }
+
+    fn query(&mut self, packet: &str) -> Option<String> {
+        match packet {
+            "QStartNoAckMode" => {
+                // The reply to this is still acknowledged
+                self.no_ack = true;
+                Some("OK".into())
+            },
+            "qAttached" => Some("1".into()),
+            "qfThreadInfo" => Some("m1".into()),
+            "qsThreadInfo" => Some("l".into()),
+            "qC" => Some("QC1".into()),
+            _ if packet.starts_with("qSupported") =>
+                Some("PacketSize=4000;qXfer:features:read+;QStartNoAckMode+".into()),
+            _ => {
+                let (offset, length) = packet.strip_prefix("qXfer:features:read:target.xml:")?.split_once(',')?;
+                let (offset, length) = (parse_hex(offset)? as usize, parse_hex(length)? as usize);
+                let xml = TARGET_XML.as_bytes();
+                let chunk = &xml[offset.min(xml.len()) .. (offset + length).min(xml.len())];
+                let marker = if offset + length < xml.len() { 'm' } else { 'l' };
+                Some(format!("{marker}{}", String::from_utf8_lossy(chunk)))
+            }
+        }
+    }
+
+    fn write_registers(&mut self, args: &str) -> Option<String> {
+        if args.len() != Register::ALL.len() * 8 {
+            return Some("E01".into());
+        }
+        let values = Register::ALL.iter().enumerate()
+            .map(|(i, _)| u32::from_str_radix(&args[i * 8 .. i * 8 + 8], 16).ok())
+            .collect::<Option<Vec<u32>>>();
+        let Some(values) = values else {
+            return Some("E01".into());
+        };
+        for (register, value) in Register::ALL.iter().zip(values) {
+            register.set(&mut self.emulator, value);
+        }
+        Some("OK".into())
+    }
+
+    fn write_register(&mut self, args: &str) -> Option<String> {
+        let (index, value) = args.split_once('=')?;
+        let register = Register::ALL.get(parse_hex(index)? as usize)?;
+        register.set(&mut self.emulator, parse_hex(value)?);
+        Some("OK".into())
+    }
+
+    /// The range of RAM covered by an address and length, if it's all in RAM.
+    fn range(&self, args: &str) -> Option<(usize, usize)> {
+        let (addr, length) = args.split_once(',')?;
+        let (addr, length) = (parse_hex(addr)? as usize, parse_hex(length)? as usize);
+        (addr.checked_add(length)? <= self.emulator.memory.len()).then_some((addr, length))
+    }
+
+    fn read_memory(&mut self, args: &str) -> Option<String> {
+        let Some((addr, length)) = self.range(args) else {
+            return Some("E01".into());
+        };
+        let reply = (addr .. addr + length)
+            .map(|i| self.software.get(&(i as u32)).copied().unwrap_or(self.emulator.memory[i]))
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        Some(reply)
+    }
+
+    fn write_memory(&mut self, args: &str) -> Option<String> {
+        let Some((range, data)) = args.split_once(':') else {
+            return Some("E01".into());
+        };
+        let Some((addr, length)) = self.range(range) else {
+            return Some("E01".into());
+        };
+        // The whole payload is checked before anything is written, so a bad one changes nothing
+        if data.len() != length * 2 || !data.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Some("E01".into());
+        }
+        let bytes = (0..length).map(|i| u8::from_str_radix(&data[i * 2 .. i * 2 + 2], 16).unwrap());
+        for (i, byte) in bytes.enumerate() {
+            // Writing over a software breakpoint changes the instruction underneath it
+            match self.software.get_mut(&((addr + i) as u32)) {
+                Some(original) => *original = byte,
+                None => self.emulator.memory[addr + i] = byte
+            }
+        }
+        Some("OK".into())
+    }
+
+    /// Inserts or removes a breakpoint or watchpoint, from the arguments of `Z` or `z`.
+    fn set_breakpoint(&mut self, args: &str, insert: bool) -> Option<String> {
+        let mut parts = args.split(';').next()?.split(',');
+        let kind = parts.next()?.parse::<u8>().ok()?;
+        let addr = parse_hex(parts.next()?)?;
+        let length = parse_hex(parts.next()?)?;
+        match (kind, insert) {
+            (0, true) => {
+                let Some(&original) = self.emulator.memory.get(addr as usize) else {
+                    return Some("E01".into());
+                };
+                if let Entry::Vacant(entry) = self.software.entry(addr) {
+                    entry.insert(original);
+                    self.emulator.memory[addr as usize] = BREAK;
+                }
+            },
+            (0, false) => {
+                if let Some(original) = self.software.remove(&addr) {
+                    self.emulator.memory[addr as usize] = original;
+                }
+            },
+            (1, true) => {
+                self.emulator.add_breakpoint(addr);
+            },
+            (1, false) => {
+                self.emulator.remove_breakpoint(addr);
+            },
+            (2..=4, true) => {
+                let watch = match kind {
+                    2 => WatchKind::Write,
+                    3 => WatchKind::Read,
+                    _ => WatchKind::Access
+                };
+                let Some(end) = addr.checked_add(length) else {
+                    return Some("E01".into());
+                };
+                if !self.watchpoints.contains_key(&(kind, addr, length)) {
+                    let id = self.emulator.add_watchpoint(addr..end, watch);
+                    self.watchpoints.insert((kind, addr, length), id);
+                }
+            },
+            (2..=4, false) => {
+                if let Some(id) = self.watchpoints.remove(&(kind, addr, length)) {
+                    self.emulator.remove_watchpoint(id);
+                }
+            },
+            _ => return None
+        }
+        Some("OK".into())
+    }
+
+    /// Steps once, running the instruction under a software breakpoint at `CUR` if there is one,
+    /// and returns why the emulator stopped.
+    fn step_over(&mut self) -> Option<StopReason> {
+        let cur = self.emulator.cur;
+        let Some(&original) = self.software.get(&cur) else {
+            return self.emulator.step_watched();
+        };
+        self.emulator.memory[cur as usize] = original;
+        let reason = self.emulator.step_watched();
+        // The step may have overwritten the instruction, so it's saved again
+        self.software.insert(cur, self.emulator.memory[cur as usize]);
+        self.emulator.memory[cur as usize] = BREAK;
+        reason
+    }
+
+    fn step(&mut self) -> String {
+        let reason = self.step_over();
+        self.stop_reply(reason)
+    }
+
+    /// The stop reply for why the emulator stopped, or for a plain trap if it just finished a step.
+    fn stop_reply(&self, reason: Option<StopReason>) -> String {
+        match reason {
+            None | Some(StopReason::Breakpoint(_) | StopReason::Condition {..}) => "S05".into(),
+            Some(StopReason::Watchpoint {id, access}) => {
+                let kind = self.watchpoints.iter().find(|(_, other)| **other == id).map(|((kind, ..), _)| *kind);
+                let kind = match kind {
+                    Some(2) => "watch",
+                    Some(3) => "rwatch",
+                    _ => "awatch"
+                };
+                format!("T05{kind}:{:x};", access.addr)
+            },
+            Some(StopReason::Interrupt(interrupt)) => match interrupt {
+                Interrupt::Halt {..} => "W00".into(),
+                Interrupt::User {code, ..} => format!("W{:02x}", code as u8),
+                Interrupt::OutOfBounds {..} | Interrupt::StackOverflow {..} | Interrupt::StackUnderflow {..} =>
+                    "S0b".into(),
+                Interrupt::DivideByZero {..} => "S08".into(),
+                Interrupt::Debugger {..} | Interrupt::Device {..} => "S05".into()
+            }
+        }
+    }
+
+    /// Runs until a breakpoint or some other reason to stop, carrying on past one at `CUR`.
+    fn resume(&mut self) -> String {
+        let mut first = true;
+        loop {
+            if !first {
+                let cur = self.emulator.cur;
+                let at_break = self.emulator.memory.get(cur as usize) == Some(&BREAK);
+                if at_break || self.emulator.breakpoints.addresses().any(|addr| addr == cur) {
+                    return "S05".into();
+                }
+            }
+            first = false;
+            if let Some(reason) = self.step_over() {
+                return self.stop_reply(Some(reason));
+            }
+        }
+    }
+}
+
+/// Serves a single client on a TCP socket, like `localhost:1234`, until it's done,
+/// and then gives back the emulator.
+///
+/// # Errors
+/// Binding the socket, accepting the client, or talking to it failed.
+pub fn serve_tcp(emulator: Emulator, addr: impl ToSocketAddrs) -> io::Result<Emulator> {
+    let listener = TcpListener::bind(addr)?;
+    let (stream, _) = listener.accept()?;
+    stream.set_nodelay(true)?;
+    let mut stub = GdbStub::new(emulator, io::BufReader::new(stream.try_clone()?), stream);
+    stub.serve()?;
+    Ok(stub.into_emulator())
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, b| sum.wrapping_add(*b))
+}
+
+fn parse_hex(hex: &str) -> Option<u32> {
+    u32::from_str_radix(hex, 16).ok()
+}
@@ -0,0 +1,412 @@
+//! Memory images in the Intel HEX and Motorola S-record formats.
+//!
+//! An [`Image`] is a set of segments of memory, with an optional start address,
+//! as exchanged with other tools and hardware. Images can be read from either format
+//! and loaded into an emulator, and any range of memory or program can be written back out.
+//!
+//! Every record's checksum is checked when reading, and the image must end with an end record.
+//! For Intel HEX, that's an `01` record, and for S-records, it's an `S7`, `S8` or `S9` record.
+//! Where records overlap, the one with the higher address wins.
+//!
+//! ```rust
+//! # use lasagna::emulator::Emulator;
+//! # use lasagna::image::Image;
+//! # use lasagna::parser::assemble;
+//! let program = assemble("literal 'Hi!'\nread u8\ninterrupt").unwrap();
+//! let image = Image::from_program(&program);
+//! let hex = image.to_intel_hex();
+//! assert_eq!(hex, "\
+//! :020000040002F8
+//! :0B0000002000000004486921003018B7
+//! :0400000500020000F5
+//! :00000001FF
+//! ");
+//! assert_eq!(Image::from_intel_hex(&hex).unwrap(), image);
+//! assert_eq!(Image::from_srec(&image.to_srec()).unwrap(), image);
+//!
+//! let mut emulator = Emulator::default();
+//! image.load(&mut emulator).unwrap();
+//! assert_eq!(&emulator.memory[0x20000 .. 0x20000 + program.len()], &program);
+//! ```
+use std::fmt;
+use std::ops::Range;
+
+use crate::constants;
+use crate::emulator::Emulator;
+use crate::executable::{Executable, ExecutableError, Segment};
+
+/// How many bytes of data are written in each record.
+const RECORD_LENGTH: usize = 16;
+
+/// An error raised when an image can't be read or loaded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ImageError {
+    /// A record on the given line, counting from 1, isn't formatted correctly.
+    InvalidRecord(usize),
+    /// A record on the given line has the wrong checksum.
+    BadChecksum(usize),
+    /// A record on the given line is of a type that isn't supported, which is given.
+    UnsupportedRecord(usize, u8),
+    /// A record count on the given line doesn't match the number of data records before it.
+    BadCount(usize),
+    /// There's a record on the given line after the end record.
+    TrailingData(usize),
+    /// There's no end record.
+    MissingEnd,
+    /// A segment at the given address goes past [`MAX_MEMORY_SIZE`](constants::MAX_MEMORY_SIZE).
+    OutOfBounds(u32)
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidRecord(line) => write!(f, "invalid record on line {line}"),
+            Self::BadChecksum(line) => write!(f, "wrong checksum on line {line}"),
+            Self::UnsupportedRecord(line, kind) => write!(f, "unsupported record type {kind:02X} on line {line}"),
+            Self::BadCount(line) => write!(f, "wrong record count on line {line}"),
+            Self::TrailingData(line) => write!(f, "unexpected record after the end on line {line}"),
+            Self::MissingEnd => f.write_str("missing end record"),
+            Self::OutOfBounds(address) => write!(f, "segment at {address:#010X} doesn't fit in memory")
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+/// Segments of memory, with an optional start address. See the [module documentation](self).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Image {
+    /// The segments, in order of address.
+    pub segments: Vec<Segment>,
+    /// Where execution starts, if that's given.
+    pub start: Option<u32>
+}
+
+impl Image {
+    /// Creates an image of a program, loaded and started at `0x20000`.
+    pub fn from_program(program: &[u8]) -> Self {
+        let segment = Segment {address: constants::PROGRAM_START, data: program.to_vec()};
+        Self {segments: vec![segment], start: Some(constants::PROGRAM_START)}
+    }
+
+    /// Creates an image of a range of memory, without a start address.
+    ///
+    /// # Panics
+    /// The range goes past the end of memory.
+    pub fn from_memory(memory: &[u8], range: Range<u32>) -> Self {
+        let data = memory[range.start as usize .. range.end as usize].to_vec();
+        Self {segments: vec![Segment {address: range.start, data}], start: None}
+    }
+
+    /// Reads an image in the Intel HEX format.
+    ///
+    /// # Errors
+    /// * A record is formatted incorrectly, has the wrong checksum, or is of an unsupported type.
+    /// * There's no end record, or there are records after it.
+    ///
+    /// ```rust
+    /// # use lasagna::image::{Image, ImageError};
+    /// assert_eq!(Image::from_intel_hex(":0100000041BE\n:00000001FF").unwrap().segments[0].data, b"A");
+    /// assert_eq!(Image::from_intel_hex(":0100000041BF\n:00000001FF"), Err(ImageError::BadChecksum(1)));
+    /// assert_eq!(Image::from_intel_hex(":0100000041BE"), Err(ImageError::MissingEnd));
+    /// ```
+    pub fn from_intel_hex(text: &str) -> Result<Self, ImageError> {
+        let mut records = Vec::new();
+        let mut start = None;
+        let mut base = 0u32;
+        let mut ended = false;
+        for (i, line) in text.lines().enumerate().map(|(i, line)| (i + 1, line.trim())) {
+            if line.is_empty() {
+                continue;
+            }
+            if ended {
+                return Err(ImageError::TrailingData(i));
+            }
+            let bytes = line.strip_prefix(':').and_then(decode_hex).ok_or(ImageError::InvalidRecord(i))?;
+            if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+                return Err(ImageError::InvalidRecord(i));
+            }
+            if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+                return Err(ImageError::BadChecksum(i));
+            }
+            let offset = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+            let data = &bytes[4 .. bytes.len() - 1];
+            match (bytes[3], data.len()) {
+                (0x00, _) => {
+                    let address = base.checked_add(offset).filter(|address| address.checked_add(data.len() as u32).is_some())
+                        .ok_or(ImageError::InvalidRecord(i))?;
+                    records.push((address, data.to_vec()));
+                },
+                (0x01, 0) => ended = true,
+                // Extended segment addresses are shifted by 4 bits, and linear ones by 16
+                (0x02, 2) => base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 4,
+                (0x04, 2) => base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 16,
+                (0x03, 4) => {
+                    let segment = u16::from_be_bytes([data[0], data[1]]) as u32;
+                    start = Some((segment << 4) + u16::from_be_bytes([data[2], data[3]]) as u32);
+                },
+                (0x05, 4) => start = Some(u32::from_be_bytes(data.try_into().unwrap())),
+                (0x01..=0x05, _) => return Err(ImageError::InvalidRecord(i)),
+                (kind, _) => return Err(ImageError::UnsupportedRecord(i, kind))
+            }
+        }
+        if !ended {
+            return Err(ImageError::MissingEnd);
+        }
+        Ok(Self {segments: merge(records), start})
+    }
+
+    /// Reads an image in the Motorola S-record format. Headers are ignored.
+    ///
+    /// # Errors
+    /// * A record is formatted incorrectly, has the wrong checksum, or is of an unsupported type.
+    /// * A record count doesn't match the number of data records.
+    /// * There's no end record, or there are records after it.
+    ///
+    /// ```rust
+    /// # use lasagna::image::{Image, ImageError};
+    /// assert_eq!(Image::from_srec("S104000041BA\nS9030000FC").unwrap().segments[0].data, b"A");
+    /// assert_eq!(Image::from_srec("S104000041BA\nS4030000FC"), Err(ImageError::UnsupportedRecord(2, 4)));
+    /// ```
+    pub fn from_srec(text: &str) -> Result<Self, ImageError> {
+        let mut records = Vec::new();
+        let mut start = None;
+        let mut ended = false;
+        for (i, line) in text.lines().enumerate().map(|(i, line)| (i + 1, line.trim())) {
+            if line.is_empty() {
+                continue;
+            }
+            if ended {
+                return Err(ImageError::TrailingData(i));
+            }
+            let mut chars = line.chars();
+            let (Some('S'), Some(kind)) = (chars.next(), chars.next().and_then(|kind| kind.to_digit(10))) else {
+                return Err(ImageError::InvalidRecord(i));
+            };
+            let kind = kind as u8;
+            let bytes = decode_hex(chars.as_str()).ok_or(ImageError::InvalidRecord(i))?;
+            if bytes.len() < 2 || bytes.len() != bytes[0] as usize + 1 {
+                return Err(ImageError::InvalidRecord(i));
+            }
+            if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0xFF {
+                return Err(ImageError::BadChecksum(i));
+            }
+
+            let address_length = match kind {
+                0 | 1 | 5 | 9 => 2,
+                2 | 6 | 8 => 3,
+                3 | 7 => 4,
+                _ => return Err(ImageError::UnsupportedRecord(i, kind))
+            };
+            let fields = &bytes[1 .. bytes.len() - 1];
+            if fields.len() < address_length {
+                return Err(ImageError::InvalidRecord(i));
+            }
+            let (address, data) = fields.split_at(address_length);
+            let address = address.iter().fold(0u32, |address, b| address << 8 | *b as u32);
+            match kind {
+                1..=3 => {
+                    if address.checked_add(data.len() as u32).is_none() {
+                        return Err(ImageError::InvalidRecord(i));
+                    }
+                    records.push((address, data.to_vec()));
+                },
+                5 | 6 if address as usize != records.len() => return Err(ImageError::BadCount(i)),
+                7..=9 => {
+                    start = Some(address);
+                    ended = true;
+                },
+                _ => ()
+            }
+        }
+        if !ended {
+            return Err(ImageError::MissingEnd);
+        }
+        Ok(Self {segments: merge(records), start})
+    }
+
+    /// Writes the image in the Intel HEX format, with extended linear addresses.
+    pub fn to_intel_hex(&self) -> String {
+        let mut text = String::new();
+        let mut record = |kind: u8, offset: u16, data: &[u8]| {
+            let mut bytes = vec![data.len() as u8];
+            bytes.extend_from_slice(&offset.to_be_bytes());
+            bytes.push(kind);
+            bytes.extend_from_slice(data);
+            let checksum = bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)).wrapping_neg();
+            bytes.push(checksum);
+            text += &format!(":{}\n", encode_hex(&bytes));
+        };
+
+        let mut base = None;
+        for (address, chunk) in self.chunks() {
+            let upper = (address >> 16) as u16;
+            if base != Some(upper) {
+                record(0x04, 0, &upper.to_be_bytes());
+                base = Some(upper);
+            }
+            record(0x00, address as u16, chunk);
+        }
+        if let Some(start) = self.start {
+            record(0x05, 0, &start.to_be_bytes());
+        }
+        record(0x01, 0, &[]);
+        text
+    }
+
+    /// Writes the image in the Motorola S-record format, with the shortest addresses that fit,
+    /// a header, and a record count.
+    pub fn to_srec(&self) -> String {
+        let end = self.segments.iter()
+            .map(|segment| segment.address as u64 + segment.data.len() as u64)
+            .chain(self.start.map(|start| start as u64 + 1))
+            .max()
+            .unwrap_or(0);
+        let (data_kind, end_kind, address_length) = match end {
+            0..=0x1_0000 => (1, 9, 2),
+            0x1_0001..=0x100_0000 => (2, 8, 3),
+            _ => (3, 7, 4)
+        };
+
+        let mut text = String::new();
+        let mut record = |kind: u8, address: u32, address_length: usize, data: &[u8]| {
+            let mut bytes = vec![(address_length + data.len() + 1) as u8];
+            bytes.extend_from_slice(&address.to_be_bytes()[4 - address_length ..]);
+            bytes.extend_from_slice(data);
+            let checksum = !bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+            bytes.push(checksum);
+            text += &format!("S{kind}{}\n", encode_hex(&bytes));
+        };
+
+        record(0, 0, 2, &[]);
+        let mut count = 0u32;
+        for (address, chunk) in self.chunks() {
+            record(data_kind, address, address_length, chunk);
+            count += 1;
+        }
+        match count {
+            0..=0xFFFF => record(5, count, 2, &[]),
+            0x1_0000..=0xFF_FFFF => record(6, count, 3, &[]),
+            // Counts that don't fit are left out
+            _ => ()
+        }
+        record(end_kind, self.start.unwrap_or(0), address_length, &[]);
+        text
+    }
+
+    /// Splits the segments into chunks for records, without crossing into another 64 KiB.
+    fn chunks(&self) -> impl Iterator<Item = (u32, &[u8])> + '_ {
+        self.segments.iter().flat_map(|segment| {
+            let mut address = segment.address;
+            let mut rest = &segment.data[..];
+            std::iter::from_fn(move || {
+                if rest.is_empty() {
+                    return None;
+                }
+                let boundary = 0x1_0000 - (address as usize & 0xFFFF);
+                let (chunk, tail) = rest.split_at(rest.len().min(RECORD_LENGTH).min(boundary));
+                let item = (address, chunk);
+                address = address.wrapping_add(chunk.len() as u32);
+                rest = tail;
+                Some(item)
+            })
+        })
+    }
+
+    /// Loads the image into an emulator's memory, setting `CUR` if there's a start address.
+    /// Like [`Executable::load`], memory is grown to fit the segments, rounded up to a multiple of 64 KiB,
+    /// and the emulator's journal is cleared.
+    ///
+    /// # Errors
+    /// A segment goes past [`MAX_MEMORY_SIZE`](constants::MAX_MEMORY_SIZE), in which case nothing is loaded.
+    ///
+    /// ```rust
+    /// # use lasagna::emulator::Emulator;
+    /// # use lasagna::executable::Segment;
+    /// # use lasagna::image::{Image, ImageError};
+    /// let mut emulator = Emulator::default();
+    /// let image = Image {segments: vec![Segment {address: 0x1FFFFF, data: vec![1, 2]}], start: None};
+    /// image.load(&mut emulator).unwrap();
+    /// assert_eq!(emulator.memory.len(), 0x210000);
+    /// assert_eq!(emulator.cur, 0x20000);
+    ///
+    /// let image = Image {segments: vec![Segment {address: 0xFFFFFFF, data: vec![1, 2]}], start: None};
+    /// assert_eq!(image.load(&mut emulator), Err(ImageError::OutOfBounds(0xFFFFFFF)));
+    /// ```
+    pub fn load(&self, emulator: &mut Emulator) -> Result<(), ImageError> {
+        let mut end = emulator.memory.len();
+        for segment in &self.segments {
+            let segment_end = segment.address as usize + segment.data.len();
+            if segment_end > constants::MAX_MEMORY_SIZE as usize {
+                return Err(ImageError::OutOfBounds(segment.address));
+            }
+            end = end.max(segment_end);
+        }
+
+        if let Some(journal) = &mut emulator.journal {
+            journal.clear();
+        }
+        if emulator.memory.len() < end {
+            let mut memory = vec![0; (end + 0xFFFF) & !0xFFFF];
+            memory[..emulator.memory.len()].copy_from_slice(&emulator.memory);
+            emulator.memory = memory.into_boxed_slice();
+        }
+        for segment in &self.segments {
+            let address = segment.address as usize;
+            emulator.memory[address .. address + segment.data.len()].copy_from_slice(&segment.data);
+        }
+        if let Some(start) = self.start {
+            emulator.cur = start;
+        }
+        Ok(())
+    }
+}
+
+/// Converts an image into an executable, starting at its start address or else at `0x20000`.
+/// Fails if the image needs more memory than an executable can ask for.
+impl TryFrom<Image> for Executable {
+    type Error = ExecutableError;
+
+    fn try_from(image: Image) -> Result<Self, ExecutableError> {
+        Executable::from_segments(image.segments, image.start.unwrap_or(constants::PROGRAM_START))
+    }
+}
+
+/// Converts an executable into an image, losing everything but its segments and entry point.
+impl From<&Executable> for Image {
+    fn from(executable: &Executable) -> Self {
+        Self {segments: executable.segments.clone(), start: Some(executable.entry)}
+    }
+}
+
+/// Combines records of data into segments in order of address, joining the ones that touch.
+fn merge(mut records: Vec<(u32, Vec<u8>)>) -> Vec<Segment> {
+    records.sort_by_key(|(address, _)| *address);
+    let mut segments: Vec<Segment> = Vec::new();
+    for (address, data) in records {
+        match segments.last_mut() {
+            Some(last) if address as u64 <= last.address as u64 + last.data.len() as u64 => {
+                let offset = (address - last.address) as usize;
+                let end = offset + data.len();
+                if end > last.data.len() {
+                    last.data.resize(end, 0);
+                }
+                last.data[offset..end].copy_from_slice(&data);
+            },
+            _ => segments.push(Segment {address, data})
+        }
+    }
+    segments
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i .. i + 2], 16).ok()).collect()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02X}")).collect()
+}
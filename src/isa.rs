@@ -0,0 +1,351 @@
+//! The instruction set, shared between the assembler, disassembler and emulator.
+//!
+//! ```rust
+//! # use lasagna::isa::{Instruction, Type};
+//! let (instruction, length) = Instruction::decode(&[0b10_000_101, 0x00]).unwrap();
+//! assert_eq!((instruction.clone(), length), (Instruction::Add(Type::I32), 1));
+//! assert_eq!(instruction.to_string(), "add i32");
+//! assert_eq!(instruction.encode(), [0b10_000_101]);
+//! ```
+use std::fmt;
+
+use crate::constants;
+
+/// One of the eight types an instruction can operate on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Type {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    Float,
+    Bool
+}
+
+impl Type {
+    /// Every type, in the order of its bit representation.
+    pub const ALL: [Type; 8] = [
+        Type::U8, Type::I8, Type::U16, Type::I16,
+        Type::U32, Type::I32, Type::Float, Type::Bool
+    ];
+
+    /// Gets a type from its textual name, e.g. `u16` or `float`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "u8" => Self::U8,
+            "i8" => Self::I8,
+            "u16" => Self::U16,
+            "i16" => Self::I16,
+            "u32" => Self::U32,
+            "i32" => Self::I32,
+            "float" => Self::Float,
+            "bool" => Self::Bool,
+            _ => return None
+        })
+    }
+
+    /// Gets the textual name of this type.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::U8 => "u8",
+            Self::I8 => "i8",
+            Self::U16 => "u16",
+            Self::I16 => "i16",
+            Self::U32 => "u32",
+            Self::I32 => "i32",
+            Self::Float => "float",
+            Self::Bool => "bool"
+        }
+    }
+
+    /// Gets the 3 bits this type is represented by in an instruction.
+    pub fn bits(self) -> u8 {
+        self as u8
+    }
+
+    /// Gets the type represented by the lowest 3 bits of an instruction.
+    pub fn from_bits(bits: u8) -> Self {
+        Self::ALL[(bits & constants::TYPE) as usize]
+    }
+
+    /// Gets the size of this type in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            Self::U8 | Self::I8 | Self::Bool => 1,
+            Self::U16 | Self::I16 => 2,
+            Self::U32 | Self::I32 | Self::Float => 4
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A single instruction.
+///
+/// Instructions that work on any type still hold the type they're encoded with,
+/// so that decoding and then encoding gives back the same bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    Noop(Type),
+    Push(Type),
+    Pop(Type),
+    Interrupt(Type),
+    /// Writes its data at `PTR`, and skips past it.
+    Literal(Vec<u8>),
+    /// Copies `VAL1` into `VAL2`.
+    /// This can't be encoded with [`Type::U8`], as that's the encoding of [`Instruction::Literal`].
+    Copy(Type),
+    Swap(Type),
+    Read(Type),
+    Write(Type),
+    Jump(Type),
+    Branch(Type),
+    BranchZero(Type),
+    Goto(Type),
+    Left(Type),
+    Right(Type),
+    Move(Type),
+    Pointer(Type),
+    Add(Type),
+    Subtract(Type),
+    Multiply(Type),
+    Divide(Type),
+    Compare(Type),
+    And(Type),
+    Or(Type),
+    Not(Type),
+    /// Casts `VAL1` between two different types.
+    /// Casts to the same type are the encodings of the instructions below.
+    Cast {
+        from: Type,
+        to: Type
+    },
+    ShiftLeft,
+    ShiftRight,
+    RotateLeft,
+    RotateRight,
+    /// XORs the first N bytes of `VAL1` and `VAL2`.
+    /// Only the type's size is encoded, so this decodes to [`Type::U8`], [`Type::U16`] or [`Type::U32`].
+    Xor(Type),
+    Break
+}
+
+impl Instruction {
+    /// Decodes the instruction at the start of some bytecode, returning it and its length in bytes.
+    /// Returns `None` if there are no bytes, or if a `literal` runs past the end.
+    ///
+    /// ```rust
+    /// # use lasagna::isa::{Instruction, Type};
+    /// assert_eq!(
+    ///     Instruction::decode(&[0x20, 0x00, 0x00, 0x00, 0x02, 0xAB, 0xCD, 0x00]),
+    ///     Some((Instruction::Literal(vec![0xAB, 0xCD]), 7))
+    /// );
+    /// assert_eq!(
+    ///     Instruction::decode(&[0b11_010_110]),
+    ///     Some((Instruction::Cast {from: Type::U16, to: Type::Float}, 1))
+    /// );
+    /// assert_eq!(Instruction::decode(&[0x20, 0x00, 0x00, 0x00, 0x02, 0xAB]), None);
+    /// ```
+    pub fn decode(bytes: &[u8]) -> Option<(Self, usize)> {
+        use Instruction::*;
+        let instr = *bytes.first()?;
+        let ty = Type::from_bits(instr);
+        let instruction = match (
+            (instr & constants::GROUP) >> 6,
+            (instr & constants::INDEX) >> 3,
+            instr & constants::TYPE
+        ) {
+            (0b00, 0b100, 0b000) => {
+                let length = u32::from_be_bytes(bytes.get(1..5)?.try_into().unwrap()) as usize;
+                let data = bytes.get(5..)?.get(..length)?;
+                return Some((Literal(data.to_vec()), 5 + length));
+            },
+            (0b00, index, _) => [Noop, Push, Pop, Interrupt, Copy, Swap, Read, Write][index as usize](ty),
+            (0b01, index, _) => [
+                Jump, Branch, BranchZero, Goto, Left, Right, Move, Pointer
+            ][index as usize](ty),
+            (0b10, index, _) => [
+                Add, Subtract, Multiply, Divide, Compare, And, Or, Not
+            ][index as usize](ty),
+            (_, from, to) if from == to => match from {
+                0b000 => ShiftLeft,
+                0b001 => ShiftRight,
+                0b010 => RotateLeft,
+                0b011 => RotateRight,
+                0b100 => Xor(Type::U8),
+                0b101 => Xor(Type::U16),
+                0b110 => Xor(Type::U32),
+                _ => Break
+            },
+            (_, from, to) => Cast {from: Type::from_bits(from), to: Type::from_bits(to)}
+        };
+        Some((instruction, 1))
+    }
+
+    /// Encodes this instruction, appending it to some bytecode.
+    ///
+    /// # Panics
+    /// * This is a [`Instruction::Copy`] of [`Type::U8`], or a [`Instruction::Cast`] to the same type.
+    /// * This is a [`Instruction::Literal`] with more than `u32::MAX` bytes of data.
+    #[allow(clippy::identity_op)] // Keeps the opcodes lined up
+    pub fn encode_into(&self, output: &mut Vec<u8>) {
+        use Instruction::*;
+        let opcode = match self {
+            Literal(data) => {
+                let length = u32::try_from(data.len()).expect("literal data should fit in a u32");
+                output.push(0b00_100_000);
+                output.extend_from_slice(&length.to_be_bytes());
+                output.extend_from_slice(data);
+                return;
+            },
+            Copy(Type::U8) => panic!("copy can't be encoded with u8"),
+            Cast {from, to} if from == to => panic!("can't cast from {from} to itself"),
+
+            Noop(ty) => 0b00_000_000 | ty.bits(),
+            Push(ty) => 0b00_001_000 | ty.bits(),
+            Pop(ty) => 0b00_010_000 | ty.bits(),
+            Interrupt(ty) => 0b00_011_000 | ty.bits(),
+            Copy(ty) => 0b00_100_000 | ty.bits(),
+            Swap(ty) => 0b00_101_000 | ty.bits(),
+            Read(ty) => 0b00_110_000 | ty.bits(),
+            Write(ty) => 0b00_111_000 | ty.bits(),
+            Jump(ty) => 0b01_000_000 | ty.bits(),
+            Branch(ty) => 0b01_001_000 | ty.bits(),
+            BranchZero(ty) => 0b01_010_000 | ty.bits(),
+            Goto(ty) => 0b01_011_000 | ty.bits(),
+            Left(ty) => 0b01_100_000 | ty.bits(),
+            Right(ty) => 0b01_101_000 | ty.bits(),
+            Move(ty) => 0b01_110_000 | ty.bits(),
+            Pointer(ty) => 0b01_111_000 | ty.bits(),
+            Add(ty) => 0b10_000_000 | ty.bits(),
+            Subtract(ty) => 0b10_001_000 | ty.bits(),
+            Multiply(ty) => 0b10_010_000 | ty.bits(),
+            Divide(ty) => 0b10_011_000 | ty.bits(),
+            Compare(ty) => 0b10_100_000 | ty.bits(),
+            And(ty) => 0b10_101_000 | ty.bits(),
+            Or(ty) => 0b10_110_000 | ty.bits(),
+            Not(ty) => 0b10_111_000 | ty.bits(),
+            Cast {from, to} => 0b11_000_000 | (from.bits() << 3) | to.bits(),
+            ShiftLeft => 0b11_000_000,
+            ShiftRight => 0b11_001_001,
+            RotateLeft => 0b11_010_010,
+            RotateRight => 0b11_011_011,
+            Xor(ty) => match ty.size() {
+                1 => 0b11_100_100,
+                2 => 0b11_101_101,
+                _ => 0b11_110_110
+            },
+            Break => 0b11_111_111
+        };
+        output.push(opcode);
+    }
+
+    /// Encodes this instruction into bytecode.
+    /// See [`Instruction::encode_into`] for when this panics.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut output = Vec::with_capacity(self.len());
+        self.encode_into(&mut output);
+        output
+    }
+
+    /// The amount of bytes this instruction takes up when encoded.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Literal(data) => 5 + data.len(),
+            _ => 1
+        }
+    }
+
+    /// Gets the textual name of this instruction.
+    pub fn mnemonic(&self) -> &'static str {
+        use Instruction::*;
+        match self {
+            Noop(_) => "noop",
+            Push(_) => "push",
+            Pop(_) => "pop",
+            Interrupt(_) => "interrupt",
+            Literal(_) => "literal",
+            Copy(_) => "copy",
+            Swap(_) => "swap",
+            Read(_) => "read",
+            Write(_) => "write",
+            Jump(_) => "jump",
+            Branch(_) => "branch",
+            BranchZero(_) => "branchzero",
+            Goto(_) => "goto",
+            Left(_) => "left",
+            Right(_) => "right",
+            Move(_) => "move",
+            Pointer(_) => "pointer",
+            Add(_) => "add",
+            Subtract(_) => "subtract",
+            Multiply(_) => "multiply",
+            Divide(_) => "divide",
+            Compare(_) => "compare",
+            And(_) => "and",
+            Or(_) => "or",
+            Not(_) => "not",
+            Cast {..} => "cast",
+            ShiftLeft => "shiftleft",
+            ShiftRight => "shiftright",
+            RotateLeft => "rotleft",
+            RotateRight => "rotright",
+            Xor(_) => "xor",
+            Break => "break"
+        }
+    }
+}
+
+/// Writes a literal's data, as a string if it looks like one, or as bytes otherwise.
+fn write_data(f: &mut fmt::Formatter<'_>, data: &[u8]) -> fmt::Result {
+    if let Some((0, text)) = data.split_last() {
+        if text.iter().all(|&b| b != 0 && (b.is_ascii_graphic() || b == b' ' || b == b'\n' || b == b'\t')) {
+            f.write_str(" '")?;
+            for &b in text {
+                match b {
+                    b'\n' => f.write_str("\\n")?,
+                    b'\t' => f.write_str("\\t")?,
+                    b'\\' => f.write_str("\\\\")?,
+                    b'\'' => f.write_str("\\'")?,
+                    _ => write!(f, "{}", b as char)?
+                }
+            }
+            return f.write_str("'");
+        }
+    }
+    for b in data {
+        write!(f, " 0x{b:02X}_u8")?;
+    }
+    Ok(())
+}
+
+/// Formats the instruction as it would be written in a program.
+/// Types are left out where they don't matter and the assembler would pick the same one.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Instruction::*;
+        let name = self.mnemonic();
+        match self {
+            Literal(data) => {
+                f.write_str(name)?;
+                write_data(f, data)
+            },
+            Copy(Type::I8) => f.write_str(name),
+            Noop(Type::U8) | Push(Type::U8) | Pop(Type::U8) | Interrupt(Type::U8) | Swap(Type::U8)
+                | Jump(Type::U8) | Goto(Type::U8) | Move(Type::U8) | Pointer(Type::U8) => f.write_str(name),
+            Noop(ty) | Push(ty) | Pop(ty) | Interrupt(ty) | Copy(ty) | Swap(ty) | Read(ty) | Write(ty)
+                | Jump(ty) | Branch(ty) | BranchZero(ty) | Goto(ty) | Left(ty) | Right(ty) | Move(ty)
+                | Pointer(ty) | Add(ty) | Subtract(ty) | Multiply(ty) | Divide(ty) | Compare(ty)
+                | And(ty) | Or(ty) | Not(ty) | Xor(ty) => write!(f, "{name} {ty}"),
+            Cast {from, to} => write!(f, "{name} {from} {to}"),
+            ShiftLeft | ShiftRight | RotateLeft | RotateRight | Break => f.write_str(name)
+        }
+    }
+}
@@ -0,0 +1,255 @@
+//! Recording execution, so that an emulator can be stepped backwards.
+//!
+//! While an emulator has a [`Journal`], every step records the registers from before it,
+//! along with the bytes of memory it overwrote, including through `literal`, `write` and the stack.
+//! Changes made by the debugger or syscalls are recorded too, by comparing memory from before
+//! and after they run, which copies all of memory for each of them.
+//! Writes to devices on the bus aren't recorded, and so aren't undone either.
+//!
+//! ```rust
+//! # use lasagna::emulator::{Emulator, Interrupt};
+//! # use lasagna::journal::Journal;
+//! # use lasagna::parser::assemble;
+//! let program = assemble("
+//!     literal 0x1234_u16
+//!     read u16
+//!     push
+//!     literal 0_u16
+//!     interrupt
+//! ").unwrap();
+//! let mut emulator = Emulator::default();
+//! emulator.memory[0x20000 .. 0x20000 + program.len()].copy_from_slice(&program);
+//! emulator.journal = Some(Journal::new(1 << 20));
+//!
+//! assert!(emulator.find_map(|result| result).is_some());
+//! assert_eq!(&emulator.memory[..2], &[0, 0]);
+//!
+//! // Go back to just after the value was pushed
+//! assert!(emulator.run_back_until(|emulator| emulator.memory[..2] == [0x12, 0x34]));
+//! assert_eq!(emulator.journal.as_ref().unwrap().index(), 3);
+//! assert_eq!(emulator.pop(), Some([0x12, 0x34, 0, 0]));
+//!
+//! // And then all the way back to the start
+//! emulator.seek(0).unwrap();
+//! assert_eq!(emulator.cur, 0x20000);
+//! assert_eq!(&emulator.memory[..2], &[0, 0]);
+//! ```
+use std::collections::VecDeque;
+use std::fmt;
+use std::mem::size_of;
+
+use crate::emulator::{Emulator, Interrupt};
+
+/// The state needed to undo a single step.
+#[derive(Debug, Clone)]
+struct Entry {
+    val1: [u8; 4],
+    val2: [u8; 4],
+    ptr:  u32,
+    cur:  u32,
+    stat: u32,
+    /// The bytes overwritten by the step, in the order they were overwritten.
+    writes: Vec<(u32, Box<[u8]>)>
+}
+
+impl Entry {
+    /// Roughly how many bytes this entry takes up.
+    fn size(&self) -> usize {
+        size_of::<Self>() + self.writes.iter()
+            .map(|(_, data)| size_of::<(u32, Box<[u8]>)>() + data.len())
+            .sum::<usize>()
+    }
+}
+
+/// A record of the steps an emulator has taken. See the [module documentation](self).
+///
+/// The oldest steps are forgotten once the journal takes up more than its cap.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    entries: VecDeque<Entry>,
+    /// The entry for the step being taken.
+    current: Option<Entry>,
+    cap: usize,
+    size: usize,
+    index: u64
+}
+
+impl Journal {
+    /// Creates an empty journal, which takes up at most about `cap` bytes.
+    pub fn new(cap: usize) -> Self {
+        Self {entries: VecDeque::new(), current: None, cap, size: 0, index: 0}
+    }
+
+    /// The index of the next step to be taken, counting from when the journal was created.
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    /// The index of the earliest step that can be gone back to.
+    pub fn oldest(&self) -> u64 {
+        self.index - self.entries.len() as u64
+    }
+
+    /// The number of steps that can be gone back.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Checks if there are no steps to go back.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Roughly how many bytes the journal takes up.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Forgets every step, without changing the index.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.size = 0;
+    }
+
+    /// Starts recording a step, from the emulator's registers.
+    pub(crate) fn begin(&mut self, emulator: &Emulator) {
+        self.current = Some(Entry {
+            val1: emulator.val1,
+            val2: emulator.val2,
+            ptr: emulator.ptr,
+            cur: emulator.cur,
+            stat: emulator.stat,
+            writes: Vec::new()
+        });
+    }
+
+    /// Records bytes of memory at `addr`, before they're overwritten.
+    pub(crate) fn overwrite(&mut self, addr: u32, old: &[u8]) {
+        if let Some(entry) = &mut self.current {
+            entry.writes.push((addr, old.into()));
+        }
+    }
+
+    /// Finishes recording a step, forgetting the oldest ones if it goes over the cap.
+    pub(crate) fn commit(&mut self) {
+        let Some(entry) = self.current.take() else {
+            return;
+        };
+        self.size += entry.size();
+        self.entries.push_back(entry);
+        self.index += 1;
+        while self.size > self.cap {
+            let Some(oldest) = self.entries.pop_front() else {
+                break;
+            };
+            self.size -= oldest.size();
+        }
+    }
+}
+
+/// An error raised when an emulator can't [seek](Emulator::seek) to a step.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SeekError {
+    /// The emulator has no journal.
+    NotRecording,
+    /// The step was forgotten, with the given index being the earliest one remembered.
+    Forgotten(u64),
+    /// Going forward was interrupted before reaching the step.
+    Interrupted(Interrupt)
+}
+
+impl fmt::Display for SeekError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotRecording => f.write_str("the emulator isn't recording"),
+            Self::Forgotten(oldest) => write!(f, "steps before {oldest} were forgotten"),
+            Self::Interrupted(interrupt) => write!(f, "interrupted: {interrupt}")
+        }
+    }
+}
+
+impl std::error::Error for SeekError {}
+
+impl Emulator {
+    /// Undoes the last step recorded in the journal.
+    /// Returns `false` if there's no journal, or nothing in it.
+    ///
+    /// Steps that ran a syscall are undone along with whatever the syscall changed:
+    /// ```rust
+    /// # use lasagna::emulator::Emulator;
+    /// # use lasagna::journal::Journal;
+    /// # use lasagna::syscall::{self, READ_LINE};
+    /// let mut emulator = Emulator::builder().val1(READ_LINE.to_be_bytes()).val2([0, 0, 0, 16]).build().unwrap()
+    ///     .with_syscall(READ_LINE, syscall::read_line(&b"hi\n"[..]))
+    ///     .with_syscall(1, |emulator| {
+    ///         emulator.memory[0x100] = 0xAB;
+    ///         lasagna::emulator::SyscallResult::Resume
+    ///     });
+    /// emulator.memory[0x20000] = 0b00_011_000; // interrupt
+    /// emulator.journal = Some(Journal::new(1 << 20));
+    ///
+    /// assert_eq!(emulator.step(), None);
+    /// assert_eq!(&emulator.memory[..3], b"hi\0");
+    /// assert!(emulator.step_back());
+    /// assert_eq!(&emulator.memory[..3], &[0; 3]);
+    ///
+    /// emulator.val1 = 1_u32.to_be_bytes();
+    /// assert_eq!(emulator.step(), None);
+    /// assert!(emulator.step_back());
+    /// assert_eq!(emulator.memory[0x100], 0);
+    /// ```
+    pub fn step_back(&mut self) -> bool {
+        let Some(journal) = &mut self.journal else {
+            return false;
+        };
+        let Some(entry) = journal.entries.pop_back() else {
+            return false;
+        };
+        journal.size -= entry.size();
+        journal.index -= 1;
+        for (addr, data) in entry.writes.iter().rev() {
+            let addr = *addr as usize;
+            self.memory[addr .. addr + data.len()].copy_from_slice(data);
+        }
+        self.val1 = entry.val1;
+        self.val2 = entry.val2;
+        self.ptr = entry.ptr;
+        self.cur = entry.cur;
+        self.stat = entry.stat;
+        true
+    }
+
+    /// Steps backwards until `predicate` is true of the emulator.
+    /// Returns `false` if the journal ran out first.
+    pub fn run_back_until(&mut self, mut predicate: impl FnMut(&Self) -> bool) -> bool {
+        while self.step_back() {
+            if predicate(self) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Steps backwards or forwards until the journal is at `index`.
+    ///
+    /// # Errors
+    /// * There's no journal.
+    /// * The step at `index` was forgotten.
+    /// * An interrupt was raised while going forwards.
+    pub fn seek(&mut self, index: u64) -> Result<(), SeekError> {
+        let journal = self.journal.as_ref().ok_or(SeekError::NotRecording)?;
+        if index < journal.oldest() {
+            return Err(SeekError::Forgotten(journal.oldest()));
+        }
+        let current = journal.index();
+        for _ in index..current {
+            self.step_back();
+        }
+        for _ in current..index {
+            if let Some(interrupt) = self.step() {
+                return Err(SeekError::Interrupted(interrupt));
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,12 +1,33 @@
 #![forbid(unsafe_code)]
 
-#[cfg(any(target_pointer_width = "16", target_pointer_width = "8"))]
+#[cfg(target_pointer_width = "16")]
 compile_error!("A target pointer width of at least 32 is required for this crate");
 
+pub mod isa;
 pub mod parser;
 pub mod emulator;
+pub mod bus;
+pub mod console;
+pub mod syscall;
+pub mod snapshot;
+pub mod executable;
+pub mod image;
+pub mod journal;
+pub mod trace;
+pub mod breakpoints;
+pub mod gdb;
+pub mod symbols;
+pub mod repl;
+pub mod tui;
+pub mod disassembler;
+mod reader;
 pub mod constants {
     pub const GROUP: u8 = 0b11000000;
     pub const INDEX: u8 = 0b00111000;
     pub const TYPE : u8 = 0b00000111;
+    /// Where `CUR` starts, and where programs are loaded.
+    pub const PROGRAM_START: u32 = 0x20000;
+    /// The most memory that executables and snapshots can ask for,
+    /// so that loading one can't allocate without limit.
+    pub const MAX_MEMORY_SIZE: u32 = 0x1000_0000;
 }
\ No newline at end of file
@@ -0,0 +1,414 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use lasagna::constants;
+use lasagna::console::{self, Console, SharedBuffer};
+use lasagna::disassembler::{disassemble, render};
+use lasagna::executable::{self, Executable};
+use lasagna::emulator::{Emulator, EmulatorBuilder};
+use lasagna::gdb::{self, GdbStub};
+use lasagna::image::Image;
+use lasagna::parser::assemble_with_symbols;
+use lasagna::repl::{self, Repl};
+use lasagna::symbols::SymbolMap;
+use lasagna::tui::Tui;
+use lasagna::syscall;
+use lasagna::trace::{Format, TraceWriter};
+
+const USAGE: &str = "\
+usage:
+    lasagna asm <input> [-o <output>] [--map <file>]
+                                        assemble a program, optionally writing a map of its labels,
+                                        in the format of the output's extension (see convert)
+    lasagna convert <input> <output> [--range <start> <end>]
+                                        convert a program to an executable (.lsgx), Intel HEX (.hex),
+                                        S-records (.srec, .s19, .s28 or .s37) or a raw program,
+                                        or export a range of memory with the program loaded
+    lasagna disasm <input>              disassemble a program
+                                        (every command takes executables, and Intel HEX and S-records
+                                        by their extension, as well as raw programs)
+    lasagna run <input> [--memory <size>] [--trace <file> | --trace-text <file>]
+                                        run a program, exiting with its interrupt code,
+                                        with a console mapped at 0000FF00 and the standard syscalls
+    lasagna debug <input> [--memory <size>] [--map <file>]
+                                        debug a program interactively, naming addresses with
+                                        the map file, or the input's .map file if there is one
+    lasagna tui <input> [--memory <size>] [--map <file>]
+                                        debug a program full-screen, like debug
+    lasagna gdb <input> [--memory <size>] [--port <port> | --stdio]
+                                        debug a program from GDB, on localhost:1234 by default,
+                                        or through stdin and stdout without the console and syscalls";
+
+/// An error that ends the program, along with the exit code to end it with.
+struct Failure(String, u8);
+
+impl Failure {
+    fn usage() -> Self {
+        Self(USAGE.into(), 2)
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, Failure> {
+    std::fs::read(path).map_err(|err| Failure(format!("couldn't read {}: {err}", path.display()), 1))
+}
+
+fn write(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), Failure> {
+    std::fs::write(path, contents).map_err(|err| Failure(format!("couldn't write {}: {err}", path.display()), 1))
+}
+
+fn asm(args: &[String]) -> Result<(), Failure> {
+    let mut input = None;
+    let mut output = None;
+    let mut map = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(PathBuf::from(args.next().ok_or_else(Failure::usage)?)),
+            "--map" => map = Some(PathBuf::from(args.next().ok_or_else(Failure::usage)?)),
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(Failure::usage())
+        }
+    }
+    let input = input.ok_or_else(Failure::usage)?;
+    let output = output.unwrap_or_else(|| Path::new(input).with_extension("bin"));
+
+    let source = String::from_utf8(read(Path::new(input))?)
+        .map_err(|_| Failure(format!("{input} isn't valid UTF-8"), 1))?;
+    let (program, symbols) = assemble_with_symbols(&source)
+        .map_err(|err| Failure(err.with_file(input.as_str()).render(), 1))?;
+    if let Some(map) = map {
+        write(&map, symbols.to_string())?;
+    }
+    let mut executable = Executable::from_program(&program).map_err(|err| Failure(format!("{input}: {err}"), 1))?;
+    executable.symbols = Some(symbols);
+    write_executable(&output, &executable)
+}
+
+fn convert(args: &[String]) -> Result<(), Failure> {
+    let mut paths = Vec::new();
+    let mut range = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--range" => {
+                let mut address = || -> Result<u32, Failure> {
+                    let address = args.next().ok_or_else(Failure::usage)?;
+                    parse_size(address).and_then(|address| address.try_into().ok())
+                        .ok_or_else(|| Failure(format!("invalid address {address}"), 2))
+                };
+                let start = address()?;
+                range = Some(start .. address()?);
+            },
+            _ => paths.push(arg)
+        }
+    }
+    let [input, output] = paths[..] else {
+        return Err(Failure::usage());
+    };
+    let executable = read_executable(input)?;
+    let Some(range) = range else {
+        return write_executable(Path::new(output), &executable);
+    };
+
+    let emulator = executable.emulator().map_err(|err| Failure(format!("{input}: {err}"), 1))?;
+    if range.start > range.end || range.end as usize > emulator.memory.len() {
+        return Err(Failure(format!("{:08X}..{:08X} isn't in memory", range.start, range.end), 1));
+    }
+    let image = Image::from_memory(&emulator.memory, range.clone());
+    let mut executable = Executable::try_from(image).map_err(|err| Failure(format!("{input}: {err}"), 1))?;
+    executable.entry = range.start;
+    write_executable(Path::new(output), &executable)
+}
+
+fn disasm(args: &[String]) -> Result<(), Failure> {
+    let [input] = args else {
+        return Err(Failure::usage());
+    };
+    for segment in read_executable(input)?.segments {
+        print!("{}", render(&disassemble(&segment.data, segment.address)));
+    }
+    Ok(())
+}
+
+/// Parses a size in bytes, in decimal or hexadecimal.
+fn parse_size(size: &str) -> Option<usize> {
+    match size.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => size.parse().ok()
+    }
+}
+
+/// The formats programs can be read and written in, going by their file's extension.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum FileFormat {
+    Executable,
+    IntelHex,
+    Srec,
+    Raw
+}
+
+impl FileFormat {
+    fn of(path: &Path) -> Self {
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+        match extension.to_ascii_lowercase().as_str() {
+            "lsgx" => Self::Executable,
+            "hex" | "ihex" => Self::IntelHex,
+            "srec" | "s19" | "s28" | "s37" => Self::Srec,
+            _ => Self::Raw
+        }
+    }
+}
+
+/// Reads a program, as an executable if it starts with the magic number or its extension says so,
+/// as Intel HEX or S-records if its extension says so, or else as a raw program.
+fn read_executable(input: &str) -> Result<Executable, Failure> {
+    let bytes = read(Path::new(input))?;
+    let invalid = |err: &dyn std::fmt::Display| Failure(format!("{input}: {err}"), 1);
+    let format = FileFormat::of(Path::new(input));
+    if format == FileFormat::Executable || bytes.starts_with(&executable::MAGIC) {
+        return Executable::from_bytes(&bytes).map_err(|err| invalid(&err));
+    }
+    if format == FileFormat::Raw {
+        return Executable::from_program(&bytes).map_err(|err| invalid(&err));
+    }
+    let text = std::str::from_utf8(&bytes).map_err(|_| Failure(format!("{input} isn't valid UTF-8"), 1))?;
+    let image = if format == FileFormat::IntelHex {Image::from_intel_hex(text)} else {Image::from_srec(text)};
+    let image = image.map_err(|err| invalid(&err))?;
+    Executable::try_from(image).map_err(|err| invalid(&err))
+}
+
+/// Writes a program in the format of the output's extension, or as a raw program.
+/// Raw programs are everything from `0x20000` to the end of the last segment.
+fn write_executable(output: &Path, executable: &Executable) -> Result<(), Failure> {
+    match FileFormat::of(output) {
+        FileFormat::Executable => write(output, executable.to_bytes()),
+        FileFormat::IntelHex => write(output, Image::from(executable).to_intel_hex()),
+        FileFormat::Srec => write(output, Image::from(executable).to_srec()),
+        FileFormat::Raw => {
+            if executable.entry != constants::PROGRAM_START
+                || executable.segments.iter().any(|segment| segment.address < constants::PROGRAM_START) {
+                return Err(Failure(format!("{} can't be written as a raw program, which starts at 00020000", output.display()), 1));
+            }
+            let end = executable.segments.iter()
+                .map(|segment| segment.address as usize + segment.data.len())
+                .max()
+                .unwrap_or(constants::PROGRAM_START as usize);
+            let memory = executable.emulator()
+                .map_err(|err| Failure(format!("{}: {err}", output.display()), 1))?
+                .memory;
+            write(output, &memory[constants::PROGRAM_START as usize .. end])
+        }
+    }
+}
+
+/// Loads a program into an emulator, returning its symbols if it has any.
+fn load(emulator: &mut Emulator, input: &str) -> Result<Option<SymbolMap>, Failure> {
+    let executable = read_executable(input)?;
+    executable.load(emulator).map_err(|err| Failure(format!("{input}: {err}"), 1))?;
+    Ok(executable.symbols)
+}
+
+fn run(args: &[String]) -> Result<u8, Failure> {
+    let mut builder = Emulator::builder();
+    let mut input = None;
+    let mut trace = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--memory" => {
+                let size = args.next().ok_or_else(Failure::usage)?;
+                let size = parse_size(size).ok_or_else(|| Failure(format!("invalid memory size {size}"), 2))?;
+                builder = builder.memory_size(size);
+            },
+            flag @ ("--trace" | "--trace-text") => {
+                let path = args.next().ok_or_else(Failure::usage)?;
+                let format = if flag == "--trace" { Format::Binary } else { Format::Text };
+                let file = File::create(path)
+                    .map_err(|err| Failure(format!("couldn't create {path}: {err}"), 1))?;
+                let writer = TraceWriter::new(BufWriter::new(file), format)
+                    .map_err(|err| Failure(format!("couldn't write {path}: {err}"), 1))?;
+                trace = Some((path, writer));
+            },
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(Failure::usage())
+        }
+    }
+    let input = input.ok_or_else(Failure::usage)?;
+
+    let emulator = builder.build().map_err(|err| Failure(err.to_string(), 2))?;
+    let mut emulator = syscall::standard(emulator, std::io::stdin(), std::io::stdout());
+    emulator.bus.map(console::RANGE, Console::stdio()).expect("nothing else should be mapped");
+    load(&mut emulator, input)?;
+    let interrupt = loop {
+        let result = match &mut trace {
+            Some((path, writer)) => writer.step(&mut emulator)
+                .map_err(|err| Failure(format!("couldn't write {path}: {err}"), 1))?,
+            None => emulator.step()
+        };
+        if let Some(interrupt) = result {
+            break interrupt;
+        }
+    };
+    if let Some((path, writer)) = trace {
+        writer.into_inner().into_inner()
+            .map_err(|err| Failure(format!("couldn't write {path}: {}", err.error()), 1))?;
+    }
+    let _ = std::io::Write::flush(&mut std::io::stdout());
+    if interrupt.is_fault() {
+        eprintln!("{interrupt}");
+        eprintln!("VAL1: {:02X?}", emulator.val1);
+        eprintln!("VAL2: {:02X?}", emulator.val2);
+        eprintln!("PTR:  {:08X}", emulator.ptr);
+        eprintln!("CUR:  {:08X}", emulator.cur);
+        eprintln!("STAT: {:08X}", emulator.stat);
+    }
+    // Exit statuses only have 8 bits, so larger codes are clamped
+    Ok(u8::try_from(interrupt.code()).unwrap_or(u8::MAX))
+}
+
+/// Reads a map file if one was given, or else uses the symbols embedded in the executable,
+/// or else the map file the assembler would've put next to the program if it's there.
+fn symbols(input: &str, map: Option<PathBuf>, embedded: Option<SymbolMap>) -> Result<SymbolMap, Failure> {
+    let map = match (map, embedded) {
+        (Some(map), _) => map,
+        (None, Some(embedded)) => return Ok(embedded),
+        (None, None) => match Some(Path::new(input).with_extension("map")).filter(|map| map.exists()) {
+            Some(map) => map,
+            None => return Ok(SymbolMap::new())
+        }
+    };
+    let text = String::from_utf8(read(&map)?)
+        .map_err(|_| Failure(format!("{} isn't valid UTF-8", map.display()), 1))?;
+    SymbolMap::parse(&text).map_err(|err| Failure(format!("{}: {err}", map.display()), 1))
+}
+
+/// Parses the arguments of the debuggers, being the input, memory size and map file.
+fn debugger_args(args: &[String]) -> Result<(&str, EmulatorBuilder, Option<PathBuf>), Failure> {
+    let mut builder = Emulator::builder();
+    let mut input = None;
+    let mut map = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--memory" => {
+                let size = args.next().ok_or_else(Failure::usage)?;
+                let size = parse_size(size).ok_or_else(|| Failure(format!("invalid memory size {size}"), 2))?;
+                builder = builder.memory_size(size);
+            },
+            "--map" => map = Some(PathBuf::from(args.next().ok_or_else(Failure::usage)?)),
+            _ if input.is_none() => input = Some(arg.as_str()),
+            _ => return Err(Failure::usage())
+        }
+    }
+    Ok((input.ok_or_else(Failure::usage)?, builder, map))
+}
+
+fn debug(args: &[String]) -> Result<(), Failure> {
+    let (input, builder, map) = debugger_args(args)?;
+    let emulator = builder.build().map_err(|err| Failure(err.to_string(), 2))?;
+    let mut emulator = syscall::standard(emulator, std::io::stdin(), std::io::stdout());
+    emulator.bus.map(console::RANGE, Console::stdio()).expect("nothing else should be mapped");
+    let embedded = load(&mut emulator, input)?;
+    let symbols = symbols(input, map, embedded)?;
+
+    // Stdin isn't locked between commands, as the program might want to read from it too
+    let mut repl = Repl::new(emulator, symbols);
+    let mut line = String::new();
+    let result = loop {
+        print!("{}", repl::PROMPT);
+        let _ = std::io::Write::flush(&mut std::io::stdout());
+        line.clear();
+        match std::io::stdin().read_line(&mut line) {
+            Ok(0) => break Ok(()),
+            Ok(_) => match repl.execute(&line, &mut std::io::stdout()) {
+                Ok(true) => (),
+                Ok(false) => break Ok(()),
+                Err(err) => break Err(err)
+            },
+            Err(err) => break Err(err)
+        }
+    };
+    result.map_err(|err| Failure(format!("couldn't debug: {err}"), 1))
+}
+
+fn tui(args: &[String]) -> Result<(), Failure> {
+    let (input, builder, map) = debugger_args(args)?;
+    // The program's output is shown in a pane, and it gets no input, as the keys go to the debugger
+    let output = SharedBuffer::new();
+    let emulator = builder.build().map_err(|err| Failure(err.to_string(), 2))?;
+    let mut emulator = syscall::standard(emulator, std::io::empty(), output.clone());
+    let console = Console::new(std::io::empty(), output.clone(), output.clone());
+    emulator.bus.map(console::RANGE, console).expect("nothing else should be mapped");
+    let embedded = load(&mut emulator, input)?;
+    let symbols = symbols(input, map, embedded)?;
+
+    let mut tui = Tui::new(emulator, symbols);
+    tui.output = Some(output);
+    tui.run().map_err(|err| Failure(format!("couldn't debug: {err}"), 1))
+}
+
+fn gdb(args: &[String]) -> Result<(), Failure> {
+    let mut builder = Emulator::builder();
+    let mut input = None;
+    let mut port = Some(1234);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--memory" => {
+                let size = args.next().ok_or_else(Failure::usage)?;
+                let size = parse_size(size).ok_or_else(|| Failure(format!("invalid memory size {size}"), 2))?;
+                builder = builder.memory_size(size);
+            },
+            "--port" => {
+                let value = args.next().ok_or_else(Failure::usage)?;
+                port = Some(value.parse().map_err(|_| Failure(format!("invalid port {value}"), 2))?);
+            },
+            "--stdio" => port = None,
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(Failure::usage())
+        }
+    }
+    let input = input.ok_or_else(Failure::usage)?;
+
+    let mut emulator = builder.build().map_err(|err| Failure(err.to_string(), 2))?;
+    let result = match port {
+        Some(port) => {
+            emulator = syscall::standard(emulator, std::io::stdin(), std::io::stdout());
+            emulator.bus.map(console::RANGE, Console::stdio()).expect("nothing else should be mapped");
+            load(&mut emulator, input)?;
+            eprintln!("waiting for GDB on localhost:{port}");
+            gdb::serve_tcp(emulator, ("127.0.0.1", port)).map(|_| ())
+        },
+        None => {
+            load(&mut emulator, input)?;
+            GdbStub::new(emulator, std::io::stdin().lock(), std::io::stdout().lock()).serve()
+        }
+    };
+    result.map_err(|err| Failure(format!("couldn't talk to GDB: {err}"), 1))
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.split_first() {
+        Some((command, args)) => match command.as_str() {
+            "asm" => asm(args).map(|_| 0),
+            "convert" => convert(args).map(|_| 0),
+            "disasm" => disasm(args).map(|_| 0),
+            "run" => run(args),
+            "debug" => debug(args).map(|_| 0),
+            "tui" => tui(args).map(|_| 0),
+            "gdb" => gdb(args).map(|_| 0),
+            _ => Err(Failure::usage())
+        },
+        None => Err(Failure::usage())
+    };
+    match result {
+        Ok(code) => ExitCode::from(code),
+        Err(Failure(message, code)) => {
+            eprintln!("{message}");
+            ExitCode::from(code)
+        }
+    }
+}
@@ -1,9 +1,38 @@
-// TODO: Rewrite parser according to new spec
-use std::str::FromStr;
+//! A two-pass assembler, turning Lasagna's textual representation into bytecode.
+//!
+//! The output is meant to be loaded into memory at `0x20000`, where `CUR` starts.
+//!
+//! ```rust
+//! # use lasagna::parser::assemble;
+//! # use lasagna::emulator::Emulator;
+//! let program = assemble("
+//!     [ Adds two numbers together, and interrupts with the sum. ]
+//!     literal 2_u32 3_u32
+//!     read u32
+//!     swap
+//!     right u32
+//!     read u32
+//!     add u32
+//!     interrupt
+//! ").unwrap();
+//!
+//! let mut emulator = Emulator::default();
+//! emulator.memory[0x20000 .. 0x20000 + program.len()].copy_from_slice(&program);
+//! let code = emulator.find_map(|result| result).map(|interrupt| interrupt.code());
+//! assert_eq!(code, Some(5));
+//! ```
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+
+use crate::constants;
+use crate::isa::Instruction;
+use crate::symbols::SymbolMap;
+pub use crate::isa::Type;
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
-enum Literal<'a> {
+pub enum Literal<'a> {
 	Float(f32),
 	Boolean(bool),
 	U8(u8),
@@ -13,41 +42,749 @@ enum Literal<'a> {
 	U32(u32),
 	I32(i32),
 	String(Cow<'a, str>),
+	/// A string containing bytes that aren't valid UTF-8.
 	Bytes(Box<[u8]>)
 }
 
+impl Literal<'_> {
+	/// Gets the bytes this literal is stored as in memory.
+	/// Strings are null-terminated, and everything else is big-endian.
+	///
+	/// ```rust
+	/// # use lasagna::parser::Literal;
+	/// assert_eq!(Literal::Float(-62.0).to_bytes(), [0xC2, 0x78, 0x00, 0x00]);
+	/// assert_eq!(Literal::I16(-2).to_bytes(), [0xFF, 0xFE]);
+	/// ```
+	pub fn to_bytes(&self) -> Vec<u8> {
+		match self {
+			Self::Float(v) => v.to_be_bytes().to_vec(),
+			Self::Boolean(v) => vec![*v as u8],
+			Self::U8(v) => vec![*v],
+			Self::I8(v) => v.to_be_bytes().to_vec(),
+			Self::U16(v) => v.to_be_bytes().to_vec(),
+			Self::I16(v) => v.to_be_bytes().to_vec(),
+			Self::U32(v) => v.to_be_bytes().to_vec(),
+			Self::I32(v) => v.to_be_bytes().to_vec(),
+			Self::String(v) => {
+				let mut bytes = v.as_bytes().to_vec();
+				bytes.push(0);
+				bytes
+			},
+			Self::Bytes(v) => {
+				let mut bytes = v.to_vec();
+				bytes.push(0);
+				bytes
+			}
+		}
+	}
+}
+
+/// Unescapes the inside of a single-quoted string.
+fn unescape(raw: &str) -> Option<Vec<u8>> {
+	let mut bytes = Vec::with_capacity(raw.len());
+	let mut chars = raw.chars();
+	while let Some(c) = chars.next() {
+		if c != '\\' {
+			let mut buf = [0; 4];
+			bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+			continue;
+		}
+		match chars.next()? {
+			'n' => bytes.push(b'\n'),
+			't' => bytes.push(b'\t'),
+			'\\' => bytes.push(b'\\'),
+			'\'' => bytes.push(b'\''),
+			'x' => {
+				let hex: String = chars.by_ref().take(2).collect();
+				if hex.len() != 2 { return None; }
+				bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+			},
+			_ => return None
+		}
+	}
+	Some(bytes)
+}
+
+/// Checks if a word is a float, with digits on both sides of the point and an optional exponent.
+fn is_float(raw: &str) -> bool {
+	let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+	let raw = raw.strip_prefix('-').unwrap_or(raw);
+	let (mantissa, exponent) = match raw.split_once(['e', 'E']) {
+		Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+		None => (raw, None)
+	};
+	let exponent_valid = exponent.is_none_or(|exponent| {
+		digits(exponent.strip_prefix(['+', '-']).unwrap_or(exponent))
+	});
+	mantissa.split_once('.').is_some_and(|(whole, fraction)| digits(whole) && digits(fraction))
+		&& exponent_valid
+}
+
+/// Parses an integer without its suffix, in decimal, hexadecimal (`0x`) or binary (`0b`),
+/// with an optional minus sign.
+fn parse_integer(raw: &str, ty: Type) -> Result<i64, ErrorKind> {
+	let (negative, unsigned) = match raw.strip_prefix('-') {
+		Some(unsigned) => (true, unsigned),
+		None => (false, raw)
+	};
+	let (radix, digits) = if let Some(digits) = unsigned.strip_prefix("0x") {
+		(16, digits)
+	} else if let Some(digits) = unsigned.strip_prefix("0b") {
+		(2, digits)
+	} else {
+		(10, unsigned)
+	};
+	if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+		return Err(ErrorKind::InvalidLiteral);
+	}
+	// Anything too big for an i64 is too big for every type anyways
+	let magnitude = i64::from_str_radix(digits, radix).map_err(|_| ErrorKind::IntegerOutOfRange(ty))?;
+	Ok(if negative {-magnitude} else {magnitude})
+}
+
+/// Splits a quoted literal into the inside of its quotes, and whatever comes after them.
+fn split_quoted(raw: &str) -> Option<(&str, &str)> {
+	let mut escaped = false;
+	let (close, _) = raw.char_indices().skip(1).find(|&(_, c)| {
+		let found = !escaped && c == '\'';
+		escaped = !escaped && c == '\\';
+		found
+	})?;
+	Some((&raw[1..close], &raw[close + 1..]))
+}
+
+impl<'a> Literal<'a> {
+	/// Creates an integer literal of the given type, checking that the value fits.
+	fn integer(value: i64, ty: Type) -> Result<Self, ErrorKind> {
+		let out_of_range = |_| ErrorKind::IntegerOutOfRange(ty);
+		Ok(match ty {
+			Type::U8 => Self::U8(value.try_into().map_err(out_of_range)?),
+			Type::I8 => Self::I8(value.try_into().map_err(out_of_range)?),
+			Type::U16 => Self::U16(value.try_into().map_err(out_of_range)?),
+			Type::I16 => Self::I16(value.try_into().map_err(out_of_range)?),
+			Type::U32 => Self::U32(value.try_into().map_err(out_of_range)?),
+			Type::I32 => Self::I32(value.try_into().map_err(out_of_range)?),
+			Type::Float | Type::Bool => return Err(ErrorKind::NonIntegerSuffix(ty))
+		})
+	}
+}
+
+/// Parses a literal, in any of these forms:
+/// * Booleans, `true` or `false`
+/// * Floats, like `0.0` or `-6.2e1`
+/// * Integers with a type suffix, like `-5_i32`, `0xFF_u8` or `0b1010_u16`
+/// * Characters with a type suffix, like `'A'_u8` or `'\n'_u32`
+/// * Strings, like `'Hello,\nworld!'`
+///
+/// Integers and characters that don't fit into their type are rejected.
+///
+/// ```rust
+/// # use lasagna::parser::{Literal, ErrorKind, Type};
+/// assert_eq!(Literal::try_from("-6.2e1"), Ok(Literal::Float(-62.0)));
+/// assert_eq!(Literal::try_from("0xFF_u8"), Ok(Literal::U8(255)));
+/// assert_eq!(Literal::try_from("-0b1_i8"), Ok(Literal::I8(-1)));
+/// assert_eq!(Literal::try_from("'A'_u16"), Ok(Literal::U16(65)));
+/// assert_eq!(Literal::try_from("300_u8"), Err(ErrorKind::IntegerOutOfRange(Type::U8)));
+/// assert_eq!(Literal::try_from("1_float"), Err(ErrorKind::NonIntegerSuffix(Type::Float)));
+/// assert_eq!(Literal::try_from("1."), Err(ErrorKind::InvalidLiteral));
+/// ```
 impl<'a> TryFrom<&'a str> for Literal<'a> {
-	type Error = ();
+	type Error = ErrorKind;
 
 	fn try_from(raw: &'a str) -> Result<Self, Self::Error> {
 		if (raw == "true") || (raw == "false") {
 			Ok(Self::Boolean(raw == "true"))
-		} else if let Some(raw) = raw.strip_suffix("_u8") {
-			raw.parse::<u8>()
-				.map(|v| Self::U8(v))
-				.map_err(|_| ())
-		} else if let Some(raw) = raw.strip_suffix("_i8") {
-			raw.parse::<i8>()
-				.map(|v| Self::I8(v))
-				.map_err(|_| ())
-		} else if let Some(raw) = raw.strip_suffix("_u16") {
-			raw.parse::<u16>()
-				.map(|v| Self::U16(v))
-				.map_err(|_| ())
-		} else if let Some(raw) = raw.strip_suffix("_i16") {
-			raw.parse::<i16>()
-				.map(|v| Self::I16(v))
-				.map_err(|_| ())
-		} else if let Some(raw) = raw.strip_suffix("_u32") {
-			raw.parse::<u32>()
-				.map(|v| Self::U32(v))
-				.map_err(|_| ())
-		} else if let Some(raw) = raw.strip_suffix("_i32") {
-			raw.parse::<i32>()
-				.map(|v| Self::I32(v))
-				.map_err(|_| ())
- 		} else if let Ok(string) = snailquote::unescape(raw) {
- 			Ok(Self::String(Cow::Owned(string)))
- 		}
- 	}
+		} else if raw.starts_with('\'') {
+			let (inner, suffix) = split_quoted(raw).ok_or(ErrorKind::UnterminatedString)?;
+			let bytes = unescape(inner).ok_or(ErrorKind::InvalidEscape)?;
+			if let Some(suffix) = suffix.strip_prefix('_') {
+				let ty = Type::from_name(suffix).ok_or_else(|| ErrorKind::BadTypeSuffix(suffix.into()))?;
+				// Escapes are single bytes, while anything else is a whole character
+				let value = match (&bytes[..], inner.chars().next()) {
+					([byte], Some('\\')) => *byte as i64,
+					(_, Some(c)) if c.len_utf8() == bytes.len() => c as i64,
+					_ => return Err(ErrorKind::InvalidLiteral)
+				};
+				return Self::integer(value, ty);
+			} else if !suffix.is_empty() {
+				return Err(ErrorKind::InvalidLiteral);
+			}
+			match String::from_utf8(bytes) {
+				Ok(string) if string == inner => Ok(Self::String(Cow::Borrowed(inner))),
+				Ok(string) => Ok(Self::String(Cow::Owned(string))),
+				Err(err) => Ok(Self::Bytes(err.into_bytes().into_boxed_slice()))
+			}
+		} else if is_float(raw) {
+			match raw.parse::<f32>() {
+				Ok(value) if value.is_finite() => Ok(Self::Float(value)),
+				_ => Err(ErrorKind::FloatOutOfRange)
+			}
+		} else if let Some((number, suffix)) = raw.rsplit_once('_') {
+			let ty = Type::from_name(suffix).ok_or_else(|| ErrorKind::BadTypeSuffix(suffix.into()))?;
+			Self::integer(parse_integer(number, ty)?, ty)
+		} else if parse_integer(raw, Type::I32).is_ok() {
+			Err(ErrorKind::BadTypeSuffix(String::new()))
+		} else {
+			Err(ErrorKind::InvalidLiteral)
+		}
+	}
+}
+
+/// A range of bytes in a program's source.
+pub type Span = Range<usize>;
+
+/// The kinds of errors that can occur while assembling a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+	/// An integer literal doesn't fit into its type.
+	IntegerOutOfRange(Type),
+	/// A float literal is too large to be represented.
+	FloatOutOfRange,
+	/// A string literal is missing its closing quote.
+	UnterminatedString,
+	/// A string literal contains an escape sequence that doesn't exist.
+	InvalidEscape,
+	/// An integer or character literal has an unknown type suffix, or none at all.
+	BadTypeSuffix(String),
+	/// An integer or character literal has the suffix of a type that isn't an integer.
+	NonIntegerSuffix(Type),
+	/// A value couldn't be parsed as any kind of literal.
+	InvalidLiteral,
+	/// A comment was never closed, or a `]` was found outside of one.
+	UnbalancedComment,
+	/// An instruction's name wasn't recognized.
+	UnknownMnemonic(String),
+	/// A type argument wasn't recognized.
+	UnknownType(String),
+	/// An instruction is missing an argument.
+	MissingArgument {
+		mnemonic: String,
+		expected: &'static str
+	},
+	/// An instruction was given more arguments than it takes.
+	UnexpectedArgument(String),
+	/// A label's name isn't a valid identifier.
+	InvalidLabel(String),
+	/// A label was jumped to, but never defined.
+	UndefinedLabel(String),
+	/// A label was defined more than once.
+	DuplicateLabel(String),
+	/// A `cast` has the same type on both sides.
+	SelfCast(Type),
+	/// The program is too large to fit into memory.
+	ProgramTooLarge
+}
+
+impl fmt::Display for ErrorKind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::IntegerOutOfRange(ty) => write!(f, "integer literal doesn't fit in a `{ty}`"),
+			Self::FloatOutOfRange => write!(f, "float literal is too large"),
+			Self::UnterminatedString => write!(f, "unterminated string"),
+			Self::InvalidEscape => write!(f, "invalid escape sequence in string"),
+			Self::BadTypeSuffix(suffix) if suffix.is_empty() =>
+				write!(f, "integer literal is missing a type suffix"),
+			Self::BadTypeSuffix(suffix) => write!(f, "unknown integer type suffix `_{suffix}`"),
+			Self::NonIntegerSuffix(Type::Float) =>
+				write!(f, "`_float` isn't a literal suffix, floats are written with a decimal point like `1.0`"),
+			Self::NonIntegerSuffix(ty) => write!(f, "`_{ty}` isn't a literal suffix, as only integer types can be"),
+			Self::InvalidLiteral => write!(f, "invalid literal"),
+			Self::UnbalancedComment => write!(f, "unbalanced comment bracket"),
+			Self::UnknownMnemonic(name) => write!(f, "unknown instruction `{name}`"),
+			Self::UnknownType(name) => write!(f, "unknown type `{name}`"),
+			Self::MissingArgument {mnemonic, expected} => write!(f, "`{mnemonic}` expects {expected}"),
+			Self::UnexpectedArgument(arg) => write!(f, "unexpected argument `{arg}`"),
+			Self::InvalidLabel(name) => write!(f, "invalid label name `{name}`"),
+			Self::UndefinedLabel(name) => write!(f, "undefined label `{name}`"),
+			Self::DuplicateLabel(name) => write!(f, "label `{name}` is defined more than once"),
+			Self::SelfCast(ty) => write!(f, "can't cast from `{ty}` to itself"),
+			Self::ProgramTooLarge => write!(f, "program doesn't fit in memory")
+		}
+	}
+}
+
+/// An error raised while assembling a program, pointing to where in the source it happened.
+///
+/// Its [`Display`](fmt::Display) implementation gives a single line,
+/// while [`AssembleError::render`] underlines the offending source.
+///
+/// ```rust
+/// # use lasagna::parser::{assemble, ErrorKind, Type};
+/// let error = assemble("noop\nliteral 300_u8").unwrap_err().with_file("main.lsg");
+/// assert_eq!(error.kind, ErrorKind::IntegerOutOfRange(Type::U8));
+/// assert_eq!((error.line, error.column, error.span.clone()), (2, 9, 13..19));
+/// assert_eq!(error.to_string(), "main.lsg:2:9: integer literal doesn't fit in a `u8`");
+/// assert_eq!(error.render(), "\
+/// error: integer literal doesn't fit in a `u8`
+///  --> main.lsg:2:9
+///   |
+/// 2 | literal 300_u8
+///   |         ^^^^^^
+/// ");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+	pub kind: ErrorKind,
+	/// The name of the file being assembled, if one was given with [`AssembleError::with_file`].
+	pub file: Option<String>,
+	/// The line the error occurred on, starting from 1.
+	pub line: usize,
+	/// The character the error starts at in its line, starting from 1.
+	pub column: usize,
+	/// The bytes of the source the error covers.
+	pub span: Span,
+	/// The text of the line the error occurred on.
+	source_line: Box<str>,
+	/// How many characters of the line the error covers.
+	width: usize
+}
+
+impl AssembleError {
+	/// Creates an error, finding where the span lands in the source.
+	pub fn new(kind: ErrorKind, span: Span, source: &str) -> Self {
+		let start = span.start.min(source.len());
+		let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+		let line_end = source[start..].find('\n').map_or(source.len(), |i| start + i);
+		let width = source[start..span.end.clamp(start, line_end)].chars().count().max(1);
+		Self {
+			kind,
+			file: None,
+			line: source[..start].matches('\n').count() + 1,
+			column: source[line_start..start].chars().count() + 1,
+			span,
+			source_line: source[line_start..line_end].trim_end_matches('\r').into(),
+			width
+		}
+	}
+
+	/// Attaches a file name to this error.
+	pub fn with_file(mut self, file: impl Into<String>) -> Self {
+		self.file = Some(file.into());
+		self
+	}
+
+	/// Renders this error in the style of rustc's diagnostics, underlining the offending source.
+	pub fn render(&self) -> String {
+		let line_number = self.line.to_string();
+		let gutter = " ".repeat(line_number.len());
+		// Tabs are kept, so the underline lines up regardless of tab width
+		let indent: String = self.source_line.chars()
+			.take(self.column - 1)
+			.map(|c| if c == '\t' {'\t'} else {' '})
+			.collect();
+		format!(
+			"error: {}\n{gutter}--> {}\n{gutter} |\n{line_number} | {}\n{gutter} | {indent}{}\n",
+			self.kind, self.location(), self.source_line, "^".repeat(self.width)
+		)
+	}
+
+	fn location(&self) -> String {
+		match &self.file {
+			Some(file) => format!("{file}:{}:{}", self.line, self.column),
+			None => format!("{}:{}", self.line, self.column)
+		}
+	}
+}
+
+impl fmt::Display for AssembleError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}: {}", self.location(), self.kind)
+	}
+}
+
+impl std::error::Error for AssembleError {}
+
+/// A single parsed line of a program.
+#[derive(Debug, Clone, PartialEq)]
+enum Statement<'a> {
+	Instruction(Instruction),
+	/// Marks the address of the next statement.
+	Label(&'a str),
+	/// A jump instruction to a label, along with where the label was named.
+	/// This writes the target address at `PTR` with a `literal`, which the jump then reads,
+	/// overwriting 4 bytes there whether or not the jump is taken.
+	Jump(Instruction, &'a str, Span)
+}
+
+impl Statement<'_> {
+	/// The amount of bytes this statement takes up in memory.
+	fn size(&self) -> usize {
+		match self {
+			Self::Instruction(instruction) => instruction.len(),
+			Self::Label(_) => 0,
+			Self::Jump(instruction, ..) => 5 + 4 + instruction.len()
+		}
+	}
+
+	fn encode(&self, output: &mut Vec<u8>, labels: &HashMap<&str, u32>) -> Result<(), (ErrorKind, Span)> {
+		match self {
+			Self::Instruction(instruction) => instruction.encode_into(output),
+			Self::Label(_) => {},
+			Self::Jump(instruction, label, span) => {
+				let target = labels.get(label)
+					.ok_or_else(|| (ErrorKind::UndefinedLabel(label.to_string()), span.clone()))?;
+				// `CUR` is incremented after jumping, so this needs to point right before the target
+				Instruction::Literal((target - 1).to_be_bytes().to_vec()).encode_into(output);
+				instruction.encode_into(output);
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Checks if a word can be used as a label name.
+/// Type names are excluded, as `jump` can take either.
+fn is_identifier(word: &str) -> bool {
+	let mut chars = word.chars();
+	chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+		&& chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+		&& Type::from_name(word).is_none()
+}
+
+/// The kinds of tokens a program is made of.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind<'a> {
+	/// A name, like that of an instruction, type or label.
+	Identifier(&'a str),
+	Literal(Literal<'a>),
+	/// A comment, including its brackets.
+	Comment(&'a str),
+	Newline
+}
+
+/// A single token, along with where it is in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token<'a> {
+	pub kind: TokenKind<'a>,
+	pub span: Span
+}
+
+/// Splits a program's source into tokens.
+///
+/// If a token is invalid, an error is given in its place, and lexing continues after it.
+///
+/// ```rust
+/// # use lasagna::parser::{Lexer, Literal, TokenKind};
+/// let tokens: Vec<_> = Lexer::new("literal 'A'_u8 [a [nested] comment]\n")
+///     .map(|token| token.unwrap())
+///     .collect();
+/// assert_eq!(tokens[0].kind, TokenKind::Identifier("literal"));
+/// assert_eq!(tokens[1].kind, TokenKind::Literal(Literal::U8(65)));
+/// assert_eq!(tokens[1].span, 8..14);
+/// assert_eq!(tokens[2].kind, TokenKind::Comment("[a [nested] comment]"));
+/// assert_eq!(tokens[3].kind, TokenKind::Newline);
+/// assert_eq!(tokens.len(), 4);
+///
+/// let mut lexer = Lexer::new("'unterminated\nnoop");
+/// assert!(lexer.next().unwrap().is_err());
+/// assert_eq!(lexer.next().unwrap().unwrap().kind, TokenKind::Newline);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Lexer<'a> {
+	source: &'a str,
+	position: usize
+}
+
+impl<'a> Lexer<'a> {
+	pub fn new(source: &'a str) -> Self {
+		Self {source, position: 0}
+	}
+
+	/// Finds the end of the comment starting at `start`, if it's ever closed.
+	fn comment_end(&self, start: usize) -> Option<usize> {
+		let mut depth = 0usize;
+		for (i, c) in self.source[start..].char_indices() {
+			match c {
+				'[' => depth += 1,
+				']' => {
+					depth -= 1;
+					if depth == 0 {
+						return Some(start + i + 1);
+					}
+				},
+				_ => {}
+			}
+		}
+		None
+	}
+
+	/// Lexes the token starting at `start`, returning it and where it ends.
+	fn lex(&self, start: usize) -> (Result<TokenKind<'a>, ErrorKind>, usize) {
+		let rest = &self.source[start..];
+		let line_end = start + rest.find('\n').unwrap_or(rest.len());
+		let word_end = |rest: &str| rest.find(|c: char| c.is_whitespace() || c == '[' || c == ']')
+			.unwrap_or(rest.len());
+		match rest.chars().next() {
+			Some('\n') => (Ok(TokenKind::Newline), start + 1),
+			Some('[') => match self.comment_end(start) {
+				Some(end) => (Ok(TokenKind::Comment(&self.source[start..end])), end),
+				// Nothing after this can be lexed properly
+				None => (Err(ErrorKind::UnbalancedComment), self.source.len())
+			},
+			Some(']') => (Err(ErrorKind::UnbalancedComment), start + 1),
+			Some('\'') => match split_quoted(rest) {
+				// Character literals have their type right after the closing quote
+				Some((inner, suffix)) => {
+					let end = start + inner.len() + 2 + word_end(suffix);
+					(Literal::try_from(&self.source[start..end]).map(TokenKind::Literal), end)
+				},
+				None => (Err(ErrorKind::UnterminatedString), line_end)
+			},
+			_ => {
+				let end = start + word_end(rest);
+				let word = &self.source[start..end];
+				let is_name = word.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+					&& word.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+				if is_name && word != "true" && word != "false" {
+					(Ok(TokenKind::Identifier(word)), end)
+				} else {
+					(Literal::try_from(word).map(TokenKind::Literal), end)
+				}
+			}
+		}
+	}
+}
+
+impl<'a> Iterator for Lexer<'a> {
+	type Item = Result<Token<'a>, AssembleError>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let rest = &self.source[self.position..];
+		let start = self.position
+			+ rest.find(|c: char| !c.is_whitespace() || c == '\n').unwrap_or(rest.len());
+		if start == self.source.len() {
+			self.position = start;
+			return None;
+		}
+		let (kind, end) = self.lex(start);
+		self.position = end;
+		Some(match kind {
+			Ok(kind) => Ok(Token {kind, span: start..end}),
+			Err(kind @ ErrorKind::UnbalancedComment) =>
+				Err(AssembleError::new(kind, start .. start + 1, self.source)),
+			Err(kind) => Err(AssembleError::new(kind, start..end, self.source))
+		})
+	}
+}
+
+/// Parses a single line's tokens into a statement.
+fn parse_statement<'a>(tokens: &[Token<'a>], source: &'a str) -> Result<Statement<'a>, (ErrorKind, Span)> {
+	let text = |token: &Token| source[token.span.clone()].to_string();
+	let (first, args) = tokens.split_first().expect("lines should not be empty");
+	let TokenKind::Identifier(mnemonic) = first.kind else {
+		return Err((ErrorKind::UnknownMnemonic(text(first)), first.span.clone()));
+	};
+	let line_span = first.span.start .. tokens.last().unwrap().span.end;
+
+	let missing = |expected| (
+		ErrorKind::MissingArgument {mnemonic: mnemonic.into(), expected},
+		line_span.clone()
+	);
+	let ty = |index: usize| -> Result<Type, (ErrorKind, Span)> {
+		let arg = args.get(index).ok_or_else(|| missing("a type"))?;
+		match arg.kind {
+			TokenKind::Identifier(name) => Type::from_name(name),
+			_ => None
+		}.ok_or_else(|| (ErrorKind::UnknownType(text(arg)), arg.span.clone()))
+	};
+	let arity = |count: usize| -> Result<(), (ErrorKind, Span)> {
+		match args.get(count) {
+			Some(arg) => Err((ErrorKind::UnexpectedArgument(text(arg)), arg.span.clone())),
+			None => Ok(())
+		}
+	};
+	let label = |index: usize| -> Result<(&'a str, Span), (ErrorKind, Span)> {
+		let arg = args.get(index).ok_or_else(|| missing("a label"))?;
+		match arg.kind {
+			TokenKind::Identifier(name) if is_identifier(name) => Ok((name, arg.span.clone())),
+			_ => Err((ErrorKind::InvalidLabel(text(arg)), arg.span.clone()))
+		}
+	};
+
+	// Instructions that ignore their type can still be given one, which only changes their encoding
+	let any = |instruction: fn(Type) -> Instruction| -> Result<(Instruction, usize), (ErrorKind, Span)> {
+		if args.is_empty() {
+			Ok((instruction(Type::U8), 0))
+		} else {
+			Ok((instruction(ty(0)?), 1))
+		}
+	};
+	let typed = |instruction: fn(Type) -> Instruction| -> Result<(Instruction, usize), (ErrorKind, Span)> {
+		Ok((instruction(ty(0)?), 1))
+	};
+
+	use Instruction::*;
+	let (instruction, arg_count) = match mnemonic {
+		"label" => {
+			arity(1)?;
+			return Ok(Statement::Label(label(0)?.0));
+		},
+		"literal" => {
+			let mut data = Vec::new();
+			for arg in args {
+				let TokenKind::Literal(literal) = &arg.kind else {
+					return Err((ErrorKind::InvalidLiteral, arg.span.clone()));
+				};
+				data.extend(literal.to_bytes());
+			}
+			return Ok(Statement::Instruction(Literal(data)));
+		},
+		"noop" => any(Noop)?,
+		"push" => any(Push)?,
+		"pop" => any(Pop)?,
+		"interrupt" => any(Interrupt)?,
+		"copy" => match any(Copy)? {
+			// A type of `u8` would make this a `literal`
+			(_, 0) => (Copy(Type::I8), 0),
+			(Copy(Type::U8), _) => return Err((
+				ErrorKind::UnexpectedArgument(Type::U8.name().into()), args[0].span.clone()
+			)),
+			parsed => parsed
+		},
+		"swap" => any(Swap)?,
+		"read" => typed(Read)?,
+		"write" => typed(Write)?,
+		"jump" if args.first().is_none_or(|arg| matches!(
+			arg.kind, TokenKind::Identifier(name) if Type::from_name(name).is_some()
+		)) => any(Jump)?,
+		"jump" => {
+			arity(1)?;
+			let (name, span) = label(0)?;
+			return Ok(Statement::Jump(Jump(Type::U8), name, span));
+		},
+		"branch" | "branchzero" => {
+			let instruction = if mnemonic == "branch" {Branch(ty(0)?)} else {BranchZero(ty(0)?)};
+			if args.len() == 1 {
+				(instruction, 1)
+			} else {
+				arity(2)?;
+				let (name, span) = label(1)?;
+				return Ok(Statement::Jump(instruction, name, span));
+			}
+		},
+		"goto" => any(Goto)?,
+		"left" => typed(Left)?,
+		"right" => typed(Right)?,
+		"move" => any(Move)?,
+		"pointer" => any(Pointer)?,
+		"add" => typed(Add)?,
+		"subtract" => typed(Subtract)?,
+		"multiply" => typed(Multiply)?,
+		"divide" => typed(Divide)?,
+		"compare" => typed(Compare)?,
+		"and" => typed(And)?,
+		"or" => typed(Or)?,
+		"not" => typed(Not)?,
+		"cast" => {
+			let (from, to) = (ty(0)?, ty(1)?);
+			if from == to {
+				return Err((ErrorKind::SelfCast(from), args[0].span.start .. args[1].span.end));
+			}
+			(Cast {from, to}, 2)
+		},
+		"shiftleft" => (ShiftLeft, 0),
+		"shiftright" => (ShiftRight, 0),
+		"rotleft" => (RotateLeft, 0),
+		"rotright" => (RotateRight, 0),
+		"xor" => typed(Xor)?,
+		"break" => (Break, 0),
+		_ => return Err((ErrorKind::UnknownMnemonic(mnemonic.into()), first.span.clone()))
+	};
+	arity(arg_count)?;
+	Ok(Statement::Instruction(instruction))
+}
+
+/// Assembles a program into bytecode, to be loaded at `0x20000`.
+///
+/// Each line holds at most one instruction, written as in the README,
+/// followed by its arguments. `literal` takes any amount of literals,
+/// which are written one after another.
+/// Instructions that work on any type can optionally be given one anyways.
+///
+/// `jump`, `branch` and `branchzero` can be given a label to jump to,
+/// in which case its address is written to memory at `PTR` right before jumping.
+/// That overwrites 4 bytes at `PTR` even when a branch isn't taken,
+/// so `PTR` should point at memory that can be spared, which the assembler doesn't check.
+/// Without a label, they jump to the address already at `PTR`.
+///
+/// ```rust
+/// # use lasagna::parser::assemble;
+/// assert_eq!(
+///     assemble("literal 1_u16 'hi'\ncast u16 float").unwrap(),
+///     [0x20, 0x00, 0x00, 0x00, 0x05, 0x00, 0x01, b'h', b'i', 0x00, 0b11_010_110]
+/// );
+/// assert!(assemble("cast u8 u8").is_err());
+/// ```
+///
+/// Labels can be used before they're defined:
+/// ```rust
+/// # use lasagna::parser::assemble;
+/// # use lasagna::emulator::Emulator;
+/// let program = assemble("
+///     jump end
+///     literal 1_u32
+///     label end
+///     interrupt
+/// ").unwrap();
+/// assert_eq!(&program[..10], &[0x20, 0, 0, 0, 4, 0x00, 0x02, 0x00, 0x12, 0x40]);
+///
+/// assert!(assemble("jump nowhere").is_err());
+/// assert!(assemble("label a\nlabel a").is_err());
+/// ```
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+	assemble_with_symbols(source).map(|(program, _)| program)
+}
+
+/// Assembles a program like [`assemble`], also giving back the address of every label.
+pub fn assemble_with_symbols(source: &str) -> Result<(Vec<u8>, SymbolMap), AssembleError> {
+	let error = |(kind, span)| AssembleError::new(kind, span, source);
+
+	// First pass: parse each line, keeping track of where it lands in memory
+	let mut statements = Vec::new();
+	let mut labels = HashMap::new();
+	let mut address = constants::PROGRAM_START;
+	let mut tokens = Lexer::new(source);
+	let mut line = Vec::new();
+	loop {
+		let token = tokens.next().transpose()?;
+		match token {
+			Some(Token {kind: TokenKind::Newline, ..}) | None => {},
+			// Comments spanning multiple lines still separate what's on either side
+			Some(Token {kind: TokenKind::Comment(comment), ..}) if comment.contains('\n') => {},
+			Some(Token {kind: TokenKind::Comment(_), ..}) => continue,
+			Some(token) => {
+				line.push(token);
+				continue;
+			}
+		}
+		if let (Some(first), Some(last)) = (line.first(), line.last()) {
+			let span = first.span.start .. last.span.end;
+			let statement = parse_statement(&line, source).map_err(error)?;
+			if let Statement::Label(name) = statement {
+				if labels.insert(name, address).is_some() {
+					return Err(error((ErrorKind::DuplicateLabel(name.into()), line[1].span.clone())));
+				}
+			}
+			address = u32::try_from(statement.size()).ok()
+				.and_then(|size| address.checked_add(size))
+				.ok_or_else(|| error((ErrorKind::ProgramTooLarge, span)))?;
+			statements.push(statement);
+			line.clear();
+		}
+		if token.is_none() {
+			break;
+		}
+	}
+
+	// Second pass: encode the statements, now that every label is known
+	let mut output = Vec::with_capacity((address - constants::PROGRAM_START) as usize);
+	for statement in &statements {
+		statement.encode(&mut output, &labels).map_err(error)?;
+	}
+	let mut symbols = SymbolMap::new();
+	for (name, address) in labels {
+		symbols.insert(name, address);
+	}
+	Ok((output, symbols))
 }
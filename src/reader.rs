@@ -0,0 +1,31 @@
+//! Reading big-endian values from the front of a slice, for the binary formats.
+
+/// An error raised when a [`Reader`] runs out of data.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Truncated;
+
+/// Reads values from the front of a slice.
+pub(crate) struct Reader<'a>(pub &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub fn take(&mut self, length: usize) -> Result<&'a [u8], Truncated> {
+        if self.0.len() < length {
+            return Err(Truncated);
+        }
+        let (taken, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], Truncated> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    pub fn u16(&mut self) -> Result<u16, Truncated> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Truncated> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+}
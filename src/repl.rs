@@ -0,0 +1,376 @@
+//! An interactive, line-based debugger for an emulator, like the one behind `lasagna debug`.
+//!
+//! Addresses and values can be given in decimal, in hexadecimal starting with `0x`,
+//! or as the name of a symbol. Entering a blank line repeats the last command.
+//!
+//! | Command                          | Description                                                     |
+//! |:---------------------------------|:----------------------------------------------------------------|
+//! | `step [n]`                       | Steps `n` instructions, or one, stopping early on an interrupt. |
+//! | `continue`                       | Runs until a breakpoint or an interrupt.                        |
+//! | `break [addr]`                   | Adds a breakpoint, or lists them all.                           |
+//! | `delete <addr>`                  | Removes a breakpoint.                                           |
+//! | `regs`                           | Shows the registers, with `VAL1` and `VAL2` as every type.      |
+//! | `x[/n] <addr>`                   | Dumps `n` bytes of memory, or 16.                               |
+//! | `stack`                          | Lists the values on the stack.                                  |
+//! | `disasm [addr]`                  | Disassembles the instructions around an address, or `CUR`.      |
+//! | `set <register> <value> [type]`  | Sets a register, to a value of the given type if there is one.  |
+//! | `help`                           | Lists the commands.                                             |
+//! | `quit`                           | Stops debugging.                                                |
+//!
+//! Commands can be shortened to their first letter, apart from `delete`, `set` and `quit`.
+//!
+//! ```rust
+//! # use lasagna::emulator::Emulator;
+//! # use lasagna::parser::assemble_with_symbols;
+//! # use lasagna::repl::Repl;
+//! let (program, symbols) = assemble_with_symbols("
+//!     literal 0x1234_u16
+//!     label main
+//!     read u16
+//!     interrupt
+//! ").unwrap();
+//! let mut emulator = Emulator::default();
+//! emulator.memory[0x20000 .. 0x20000 + program.len()].copy_from_slice(&program);
+//!
+//! let mut repl = Repl::new(emulator, symbols);
+//! let mut output = Vec::new();
+//! repl.run(&b"break main\ncontinue\nstep\nx/2 0\nquit\n"[..], &mut output).unwrap();
+//! assert_eq!(String::from_utf8(output).unwrap(), "\
+//! (lasagna) breakpoint at 00020007 <main>
+//! (lasagna) breakpoint at 00020007
+//! => [00020007] <main> read u16
+//! (lasagna) => [00020008] <main+1> interrupt
+//! (lasagna) 00000000  12 34                                             |.4|
+//! (lasagna) ");
+//! ```
+use std::io::{self, BufRead, Write};
+
+use crate::breakpoints::Register;
+use crate::disassembler::disassemble_around;
+use crate::emulator::Emulator;
+use crate::isa::{Instruction, Type};
+use crate::symbols::SymbolMap;
+
+/// What the prompt looks like.
+pub const PROMPT: &str = "(lasagna) ";
+
+/// How many instructions `disasm` shows on either side of the address.
+const CONTEXT: usize = 5;
+
+const HELP: &str = "\
+step [n]                  step n instructions, or one
+continue                  run until a breakpoint or an interrupt
+break [addr]              add a breakpoint, or list them all
+delete <addr>             remove a breakpoint
+regs                      show the registers
+x[/n] <addr>              dump n bytes of memory, or 16
+stack                     list the values on the stack
+disasm [addr]             disassemble around an address, or CUR
+set <reg> <value> [type]  set a register
+quit                      stop debugging
+";
+
+/// An interactive debugger. See the [module documentation](self).
+pub struct Repl {
+    pub emulator: Emulator,
+    pub symbols: SymbolMap,
+    /// The last command that was entered, to repeat on a blank line.
+    last: String
+}
+
+impl Repl {
+    /// Creates a debugger for an emulator, naming addresses with the given symbols.
+    pub fn new(emulator: Emulator, symbols: SymbolMap) -> Self {
+        Self {emulator, symbols, last: String::new()}
+    }
+
+    /// Prompts for commands and runs them, until `quit` or the end of the input.
+    ///
+    /// # Errors
+    /// Reading from the input or writing to the output failed.
+    pub fn run(&mut self, mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        let mut line = String::new();
+        loop {
+            output.write_all(PROMPT.as_bytes())?;
+            output.flush()?;
+            line.clear();
+            if input.read_line(&mut line)? == 0 || !self.execute(&line, &mut output)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Runs a single command, writing what it shows and any error to `output`.
+    /// Returns `false` if the command was `quit`.
+    ///
+    /// # Errors
+    /// Writing to the output failed.
+    pub fn execute(&mut self, line: &str, output: &mut impl Write) -> io::Result<bool> {
+        let line = match line.trim() {
+            "" => self.last.clone(),
+            line => line.to_string()
+        };
+        self.last.clone_from(&line);
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return Ok(true);
+        };
+        let args: Vec<&str> = words.collect();
+        let (command, count) = match command.split_once('/') {
+            Some((command, count)) => (command, Some(count)),
+            None => (command, None)
+        };
+
+        let result = match (command, count) {
+            ("quit" | "exit", None) => return Ok(false),
+            ("step" | "s", None) => self.step(&args),
+            ("continue" | "c", None) => Ok(self.resume()),
+            ("break" | "b", None) => self.add_breakpoint(&args),
+            ("delete", None) => self.delete_breakpoint(&args),
+            ("regs" | "r", None) => Ok(self.registers()),
+            ("x", _) => self.dump(count, &args),
+            ("stack", None) => Ok(self.stack()),
+            ("disasm" | "d", None) => self.disassemble(&args),
+            ("set", None) => self.set(&args),
+            ("help" | "h", None) => Ok(HELP.into()),
+            _ => Err(format!("unknown command `{line}`, try `help`\n"))
+        };
+        match result {
+            Ok(text) | Err(text) => output.write_all(text.as_bytes())?
+        }
+        Ok(true)
+    }
+
+    /// Parses an address or value, as a number or the name of a symbol.
+    fn value(&self, text: &str) -> Result<u32, String> {
+        let number = match text.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+            None => text.parse().ok()
+        };
+        number.or_else(|| self.symbols.address(text))
+            .ok_or_else(|| format!("`{text}` isn't a number or a symbol\n"))
+    }
+
+    /// Formats the instruction at an address, marking it if it's at `CUR` or a breakpoint.
+    fn instruction(&self, addr: u32) -> String {
+        let marker = if addr == self.emulator.cur {
+            "=>"
+        } else if self.emulator.breakpoints.addresses().any(|other| other == addr) {
+            " *"
+        } else {
+            "  "
+        };
+        let instruction = self.emulator.memory.get(addr as usize..)
+            .and_then(Instruction::decode)
+            .map_or_else(|| "[ out of bounds ]".into(), |(instruction, _)| instruction.to_string());
+        let symbol = symbol(&self.symbols, addr).map_or_else(String::new, |symbol| symbol + " ");
+        format!("{marker} [{addr:08X}] {symbol}{instruction}\n")
+    }
+
+    fn step(&mut self, args: &[&str]) -> Result<String, String> {
+        let count = match args {
+            [] => 1,
+            [count] => count.parse::<u64>().map_err(|_| format!("invalid count `{count}`\n"))?,
+            _ => return Err("usage: step [n]\n".into())
+        };
+        for _ in 0..count {
+            if let Some(interrupt) = self.emulator.step() {
+                return Ok(format!("{interrupt}\n{}", self.instruction(self.emulator.cur)));
+            }
+        }
+        Ok(self.instruction(self.emulator.cur))
+    }
+
+    fn resume(&mut self) -> String {
+        let reason = self.emulator.run();
+        format!("{reason}\n{}", self.instruction(self.emulator.cur))
+    }
+
+    fn add_breakpoint(&mut self, args: &[&str]) -> Result<String, String> {
+        match args {
+            [] => {
+                let list: String = self.emulator.breakpoints.addresses()
+                    .map(|addr| format!("breakpoint at {}\n", describe(&self.symbols, addr)))
+                    .collect();
+                Ok(if list.is_empty() { "no breakpoints\n".into() } else { list })
+            },
+            [addr] => {
+                let addr = self.value(addr)?;
+                if self.emulator.add_breakpoint(addr) {
+                    Ok(format!("breakpoint at {}\n", describe(&self.symbols, addr)))
+                } else {
+                    Err(format!("there's already a breakpoint at {}\n", describe(&self.symbols, addr)))
+                }
+            },
+            _ => Err("usage: break [addr]\n".into())
+        }
+    }
+
+    fn delete_breakpoint(&mut self, args: &[&str]) -> Result<String, String> {
+        let [addr] = args else {
+            return Err("usage: delete <addr>\n".into());
+        };
+        let addr = self.value(addr)?;
+        if self.emulator.remove_breakpoint(addr) {
+            Ok(String::new())
+        } else {
+            Err(format!("there's no breakpoint at {}\n", describe(&self.symbols, addr)))
+        }
+    }
+
+    fn registers(&self) -> String {
+        let mut text = String::new();
+        let [val1, val2] = [self.emulator.val1, self.emulator.val2];
+        text += &format!("{:<6}{:<16}{}\n", "", "VAL1", "VAL2");
+        text += &format!("{:<6}{:<16}{}\n", "bytes", hex(&val1), hex(&val2));
+        for ty in Type::ALL {
+            text += &format!("{:<6}{:<16}{}\n", ty.name(), interpret(val1, ty), interpret(val2, ty));
+        }
+        text += &format!("PTR   {}\n", describe(&self.symbols, self.emulator.ptr));
+        text += &format!("CUR   {}\n", describe(&self.symbols, self.emulator.cur));
+        text += &format!("STAT  {:08X}\n", self.emulator.stat);
+        text
+    }
+
+    fn dump(&self, count: Option<&str>, args: &[&str]) -> Result<String, String> {
+        let [addr] = args else {
+            return Err("usage: x[/n] <addr>\n".into());
+        };
+        let count = match count {
+            Some(count) => count.parse::<usize>().map_err(|_| format!("invalid count `{count}`\n"))?,
+            None => 16
+        };
+        let start = self.value(addr)? as usize;
+        let bytes = start.checked_add(count)
+            .and_then(|end| self.emulator.memory.get(start..end))
+            .ok_or_else(|| format!("{} bytes at {start:08X} are out of bounds\n", count))?;
+        Ok(hex_dump(start, bytes))
+    }
+
+    fn stack(&self) -> String {
+        let memory = &self.emulator.memory;
+        let length = u16::from_be_bytes([memory[0x10002], memory[0x10003]]) as usize;
+        let mut text = format!("length {length}\n");
+        for i in (0..length).rev() {
+            let addr = 0x10004 + i * 4;
+            let Some(value) = memory.get(addr .. addr + 4) else {
+                continue;
+            };
+            text += &format!("[{i}] {addr:08X}  {}\n", hex(value));
+        }
+        text
+    }
+
+    fn disassemble(&self, args: &[&str]) -> Result<String, String> {
+        let addr = match args {
+            [] => self.emulator.cur,
+            [addr] => self.value(addr)?,
+            _ => return Err("usage: disasm [addr]\n".into())
+        };
+        if addr as usize >= self.emulator.memory.len() {
+            return Err(format!("{addr:08X} is out of bounds\n"));
+        }
+        let start = crate::constants::PROGRAM_START;
+        let addresses = disassemble_around(&self.emulator.memory, start, addr, CONTEXT, CONTEXT)
+            .into_iter()
+            .map(|instruction| instruction.address);
+
+        let mut text = String::new();
+        for addr in addresses {
+            for (other, name) in self.symbols.iter() {
+                if other == addr {
+                    text += &format!("{name}:\n");
+                }
+            }
+            text += &self.instruction(addr);
+        }
+        Ok(text)
+    }
+
+    fn set(&mut self, args: &[&str]) -> Result<String, String> {
+        let (name, value, ty) = match args {
+            [name, value] => (name, value, None),
+            [name, value, ty] => (name, value, Some(Type::from_name(ty).ok_or_else(|| format!("unknown type `{ty}`\n"))?)),
+            _ => return Err("usage: set <reg> <value> [type]\n".into())
+        };
+        let register = Register::from_name(name).ok_or_else(|| format!("unknown register `{name}`\n"))?;
+        match ty {
+            Some(ty) => {
+                let bytes = encode(value, ty).ok_or_else(|| format!("`{value}` isn't a valid {}\n", ty.name()))?;
+                match register {
+                    Register::Val1 => self.emulator.val1 = bytes,
+                    Register::Val2 => self.emulator.val2 = bytes,
+                    _ => register.set(&mut self.emulator, u32::from_be_bytes(bytes))
+                }
+            },
+            None => {
+                let value = self.value(value)?;
+                register.set(&mut self.emulator, value);
+            }
+        }
+        Ok(String::new())
+    }
+}
+
+/// Formats the symbol an address is in, like `<main+1>`.
+pub(crate) fn symbol(symbols: &SymbolMap, addr: u32) -> Option<String> {
+    match symbols.locate(addr)? {
+        (name, 0) => Some(format!("<{name}>")),
+        (name, offset) => Some(format!("<{name}+{offset}>"))
+    }
+}
+
+/// Formats an address along with the symbol it's in, like `00020007 <main+1>`.
+pub(crate) fn describe(symbols: &SymbolMap, addr: u32) -> String {
+    match symbol(symbols, addr) {
+        Some(symbol) => format!("{addr:08X} {symbol}"),
+        None => format!("{addr:08X}")
+    }
+}
+
+/// Shows a register as a type, like the emulator would read it.
+pub(crate) fn interpret(value: [u8; 4], ty: Type) -> String {
+    match ty {
+        Type::U8 => value[0].to_string(),
+        Type::I8 => (value[0] as i8).to_string(),
+        Type::U16 => u16::from_be_bytes([value[0], value[1]]).to_string(),
+        Type::I16 => i16::from_be_bytes([value[0], value[1]]).to_string(),
+        Type::U32 => u32::from_be_bytes(value).to_string(),
+        Type::I32 => i32::from_be_bytes(value).to_string(),
+        Type::Float => format!("{:?}", f32::from_be_bytes(value)),
+        Type::Bool => (value[0] != 0).to_string()
+    }
+}
+
+/// Parses a value of a type into the bytes of a register.
+fn encode(value: &str, ty: Type) -> Option<[u8; 4]> {
+    let mut bytes = [0; 4];
+    match ty {
+        Type::U8 => bytes[0] = value.parse::<u8>().ok()?,
+        Type::I8 => bytes[0] = value.parse::<i8>().ok()? as u8,
+        Type::U16 => bytes[..2].copy_from_slice(&value.parse::<u16>().ok()?.to_be_bytes()),
+        Type::I16 => bytes[..2].copy_from_slice(&value.parse::<i16>().ok()?.to_be_bytes()),
+        Type::U32 => bytes = value.parse::<u32>().ok()?.to_be_bytes(),
+        Type::I32 => bytes = value.parse::<i32>().ok()?.to_be_bytes(),
+        Type::Float => bytes = value.parse::<f32>().ok()?.to_be_bytes(),
+        Type::Bool => bytes[0] = value.parse::<bool>().ok()? as u8
+    }
+    Some(bytes)
+}
+
+/// Formats bytes in hexadecimal, separated by spaces.
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02X}")).collect::<Vec<_>>().join(" ")
+}
+
+/// Formats bytes as lines of 16 in hexadecimal, followed by the printable ones.
+fn hex_dump(start: usize, bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for (i, line) in bytes.chunks(16).enumerate() {
+        let ascii: String = line.iter()
+            .map(|b| if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' })
+            .collect();
+        text += &format!("{:08X}  {:<48}  |{ascii}|\n", start + i * 16, hex(line));
+    }
+    text
+}
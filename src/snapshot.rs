@@ -0,0 +1,260 @@
+//! Saving and restoring the state of an emulator.
+//!
+//! A snapshot holds the registers and memory of an emulator, but not anything attached to it,
+//! like its debugger, callbacks, syscalls, devices or vector table.
+//! Memory is stored sparsely, as runs of bytes between long stretches of zeros.
+//!
+//! # Format
+//! Everything is big-endian.
+//!
+//! | Size  | Description                                          |
+//! |:-----:|:-----------------------------------------------------|
+//! | `4`   | The magic bytes `LSGS`.                              |
+//! | `2`   | The format version, currently `1`.                   |
+//! | `2`   | Reserved, and always `0`.                            |
+//! | `4`   | `VAL1`.                                              |
+//! | `4`   | `VAL2`.                                              |
+//! | `4`   | `PTR`.                                               |
+//! | `4`   | `CUR`.                                               |
+//! | `4`   | `STAT`.                                              |
+//! | `4`   | The size of memory, at most [`MAX_MEMORY_SIZE`].     |
+//! | `4`   | The number of runs of memory.                        |
+//! | ...   | Each run, as its address and length as u32s, and then its bytes. |
+//!
+//! Runs are in order, and don't overlap. Any memory outside of them is zero.
+//!
+//! ```rust
+//! # use lasagna::emulator::Emulator;
+//! # use lasagna::snapshot::Snapshot;
+//! let mut emulator = Emulator::builder().memory_size(16 << 20).build().unwrap();
+//! emulator.memory[0x20000 .. 0x20004].copy_from_slice(b"Hi!\0");
+//! emulator.val1 = [1, 2, 3, 4];
+//!
+//! let bytes = emulator.snapshot().to_bytes();
+//! assert!(bytes.len() < 64);
+//!
+//! let mut restored = Emulator::default();
+//! restored.restore(&Snapshot::from_bytes(&bytes).unwrap());
+//! assert_eq!(restored.val1, [1, 2, 3, 4]);
+//! assert_eq!(restored.memory, emulator.memory);
+//! ```
+use std::fmt;
+
+use crate::constants::MAX_MEMORY_SIZE;
+use crate::emulator::Emulator;
+use crate::reader::{Reader, Truncated};
+
+/// The magic bytes a snapshot starts with.
+pub const MAGIC: [u8; 4] = *b"LSGS";
+
+/// The version of the format that snapshots are written in.
+pub const VERSION: u16 = 1;
+
+/// Runs are split at stretches of zeros at least this long, as that's the size of a run's header.
+const MIN_GAP: usize = 8;
+
+/// An error raised when a snapshot can't be read.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SnapshotError {
+    /// The data doesn't start with [`MAGIC`].
+    BadMagic,
+    /// The snapshot is in a version of the format that isn't supported, which is given.
+    UnsupportedVersion(u16),
+    /// The data ended before the snapshot did.
+    Truncated,
+    /// There's more data after the end of the snapshot.
+    TrailingData,
+    /// The size of memory is too small to hold the stack, with the given size.
+    MemoryTooSmall(u32),
+    /// The size of memory is larger than [`MAX_MEMORY_SIZE`], with the given size.
+    MemoryTooLarge(u32),
+    /// A run of memory at the given address overlaps with the previous run, or goes past the end of memory.
+    InvalidRun(u32)
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic => f.write_str("not a snapshot"),
+            Self::UnsupportedVersion(version) => write!(f, "unsupported snapshot version {version}"),
+            Self::Truncated => f.write_str("snapshot is truncated"),
+            Self::TrailingData => f.write_str("unexpected data after the end of the snapshot"),
+            Self::MemoryTooSmall(size) =>
+                write!(f, "memory of {size:#X} bytes is too small to contain the stack"),
+            Self::MemoryTooLarge(size) =>
+                write!(f, "memory of {size:#X} bytes is larger than the limit of {MAX_MEMORY_SIZE:#X}"),
+            Self::InvalidRun(address) => write!(f, "invalid run of memory at {address:#010X}")
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<Truncated> for SnapshotError {
+    fn from(_: Truncated) -> Self {
+        Self::Truncated
+    }
+}
+
+/// The state of an emulator at some point. See the [module documentation](self).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub val1: [u8; 4],
+    pub val2: [u8; 4],
+    pub ptr:  u32,
+    pub cur:  u32,
+    pub stat: u32,
+    memory_size: u32,
+    /// The runs of memory that aren't zero, with their addresses.
+    runs: Vec<(u32, Box<[u8]>)>
+}
+
+impl Snapshot {
+    /// The size of the memory in this snapshot.
+    pub fn memory_size(&self) -> u32 {
+        self.memory_size
+    }
+
+    /// Writes the snapshot out in the binary format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&VERSION.to_be_bytes());
+        bytes.extend_from_slice(&[0; 2]);
+        bytes.extend_from_slice(&self.val1);
+        bytes.extend_from_slice(&self.val2);
+        for value in [self.ptr, self.cur, self.stat, self.memory_size, self.runs.len() as u32] {
+            bytes.extend_from_slice(&value.to_be_bytes());
+        }
+        for (address, data) in &self.runs {
+            bytes.extend_from_slice(&address.to_be_bytes());
+            bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(data);
+        }
+        bytes
+    }
+
+    /// Reads a snapshot in the binary format.
+    ///
+    /// # Errors
+    /// * The data isn't a snapshot, or is in a different version of the format.
+    /// * The data is cut short, or goes on past the end of the snapshot.
+    /// * The memory is too small or too large, or the runs of memory don't fit into it in order.
+    ///
+    /// ```rust
+    /// # use lasagna::snapshot::{Snapshot, SnapshotError};
+    /// assert_eq!(Snapshot::from_bytes(b"LSGS\0\x02\0\0"), Err(SnapshotError::UnsupportedVersion(2)));
+    /// assert_eq!(Snapshot::from_bytes(b"LSGS\0\x01"), Err(SnapshotError::Truncated));
+    ///
+    /// let mut huge = b"LSGS\0\x01\0\0".to_vec();
+    /// huge.extend_from_slice(&[0; 20]);
+    /// huge.extend_from_slice(&[0xFF; 4]);
+    /// huge.extend_from_slice(&[0; 4]);
+    /// assert_eq!(Snapshot::from_bytes(&huge), Err(SnapshotError::MemoryTooLarge(0xFFFFFFFF)));
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut reader = Reader(bytes);
+        if reader.take(4).map_err(|_| SnapshotError::BadMagic)? != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        reader.take(2)?;
+        let val1 = reader.array()?;
+        let val2 = reader.array()?;
+        let ptr = reader.u32()?;
+        let cur = reader.u32()?;
+        let stat = reader.u32()?;
+        let memory_size = reader.u32()?;
+        if memory_size <= 0x20000 {
+            return Err(SnapshotError::MemoryTooSmall(memory_size));
+        }
+        if memory_size > MAX_MEMORY_SIZE {
+            return Err(SnapshotError::MemoryTooLarge(memory_size));
+        }
+
+        let count = reader.u32()?;
+        let mut runs = Vec::new();
+        let mut end = 0;
+        for _ in 0..count {
+            let address = reader.u32()?;
+            let length = reader.u32()?;
+            let data = reader.take(length as usize)?;
+            if (address as u64) < end || address as u64 + length as u64 > memory_size as u64 {
+                return Err(SnapshotError::InvalidRun(address));
+            }
+            end = address as u64 + length as u64;
+            runs.push((address, data.into()));
+        }
+        if !reader.0.is_empty() {
+            return Err(SnapshotError::TrailingData);
+        }
+        Ok(Self {val1, val2, ptr, cur, stat, memory_size, runs})
+    }
+}
+
+/// Finds the runs of memory that aren't zero.
+fn runs(memory: &[u8]) -> Vec<(u32, Box<[u8]>)> {
+    let mut runs = Vec::new();
+    let mut position = 0;
+    while let Some(start) = memory[position..].iter().position(|b| *b != 0).map(|i| i + position) {
+        // The run ends at the first stretch of zeros long enough to be worth a new run
+        let mut end = start;
+        let mut zeros = 0;
+        for (i, b) in memory[start..].iter().enumerate() {
+            if *b == 0 {
+                zeros += 1;
+                if zeros == MIN_GAP {
+                    break;
+                }
+            } else {
+                zeros = 0;
+                end = start + i + 1;
+            }
+        }
+        runs.push((start as u32, memory[start..end].into()));
+        position = end;
+    }
+    runs
+}
+
+impl Emulator {
+    /// Takes a snapshot of the registers and memory of this emulator.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            val1: self.val1,
+            val2: self.val2,
+            ptr: self.ptr,
+            cur: self.cur,
+            stat: self.stat,
+            memory_size: self.memory.len() as u32,
+            runs: runs(&self.memory)
+        }
+    }
+
+    /// Restores the registers and memory of this emulator from a snapshot,
+    /// resizing memory to the size in the snapshot.
+    /// Anything attached to the emulator is kept, but its journal is cleared.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        if let Some(journal) = &mut self.journal {
+            journal.clear();
+        }
+        self.val1 = snapshot.val1;
+        self.val2 = snapshot.val2;
+        self.ptr = snapshot.ptr;
+        self.cur = snapshot.cur;
+        self.stat = snapshot.stat;
+        if self.memory.len() == snapshot.memory_size as usize {
+            self.memory.fill(0);
+        } else {
+            self.memory = vec![0; snapshot.memory_size as usize].into_boxed_slice();
+        }
+        for (address, data) in &snapshot.runs {
+            let address = *address as usize;
+            self.memory[address .. address + data.len()].copy_from_slice(data);
+        }
+    }
+}
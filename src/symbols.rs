@@ -0,0 +1,132 @@
+//! Symbol maps, naming the addresses of the labels in an assembled program.
+//!
+//! # Format
+//! A map file is text, with a symbol on each line, as its address in hexadecimal,
+//! whitespace and then its name. Blank lines are ignored.
+//!
+//! ```text
+//! 00020000 start
+//! 0002001C loop
+//! ```
+//!
+//! ```rust
+//! # use lasagna::parser::assemble_with_symbols;
+//! # use lasagna::symbols::SymbolMap;
+//! let (_, symbols) = assemble_with_symbols("
+//!     label start
+//!     literal 1_u32
+//!     label end
+//!     interrupt
+//! ").unwrap();
+//! assert_eq!(symbols.to_string(), "00020000 start\n00020009 end\n");
+//! assert_eq!(symbols.address("end"), Some(0x20009));
+//! assert_eq!(symbols.locate(0x20004), Some(("start", 4)));
+//!
+//! let parsed = SymbolMap::parse(&symbols.to_string()).unwrap();
+//! assert_eq!(parsed, symbols);
+//! ```
+use std::fmt;
+
+/// An error raised when a map file can't be read, with the line it's on, counting from 1.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SymbolError(pub usize);
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid symbol on line {}", self.0)
+    }
+}
+
+impl std::error::Error for SymbolError {}
+
+/// The names of addresses in a program. See the [module documentation](self).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolMap {
+    /// Each symbol's address and name, in order of address and then name.
+    symbols: Vec<(u32, String)>
+}
+
+impl SymbolMap {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names an address, replacing any other address with the same name.
+    pub fn insert(&mut self, name: impl Into<String>, addr: u32) {
+        let name = name.into();
+        self.symbols.retain(|(_, other)| *other != name);
+        let entry = (addr, name);
+        let index = self.symbols.partition_point(|other| *other < entry);
+        self.symbols.insert(index, entry);
+    }
+
+    /// Gets the address of a name.
+    pub fn address(&self, name: &str) -> Option<u32> {
+        self.symbols.iter().find(|(_, other)| other == name).map(|(addr, _)| *addr)
+    }
+
+    /// Gets the first name of an address.
+    pub fn name(&self, addr: u32) -> Option<&str> {
+        self.locate(addr).filter(|(_, offset)| *offset == 0).map(|(name, _)| name)
+    }
+
+    /// Finds the closest symbol at or before an address, along with how far past it the address is.
+    pub fn locate(&self, addr: u32) -> Option<(&str, u32)> {
+        let index = self.symbols.partition_point(|(other, _)| *other <= addr);
+        let (start, _) = self.symbols.get(index.checked_sub(1)?)?;
+        // The first name at that address is the one to use
+        let first = self.symbols.partition_point(|(other, _)| *other < *start);
+        Some((&self.symbols[first].1, addr - start))
+    }
+
+    /// Every symbol's address and name, in order of address.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &str)> + '_ {
+        self.symbols.iter().map(|(addr, name)| (*addr, name.as_str()))
+    }
+
+    /// The number of symbols.
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    /// Checks if there are no symbols.
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Reads a map file.
+    ///
+    /// # Errors
+    /// A line isn't an address in hexadecimal followed by a name.
+    ///
+    /// ```rust
+    /// # use lasagna::symbols::{SymbolError, SymbolMap};
+    /// assert_eq!(SymbolMap::parse("20000 main\n\nmain").unwrap_err(), SymbolError(3));
+    /// ```
+    pub fn parse(text: &str) -> Result<Self, SymbolError> {
+        let mut map = Self::new();
+        for (i, line) in text.lines().enumerate() {
+            let mut words = line.split_whitespace();
+            let Some(addr) = words.next() else {
+                continue;
+            };
+            let (Some(name), None) = (words.next(), words.next()) else {
+                return Err(SymbolError(i + 1));
+            };
+            let addr = u32::from_str_radix(addr, 16).map_err(|_| SymbolError(i + 1))?;
+            map.insert(name, addr);
+        }
+        Ok(map)
+    }
+}
+
+/// Writes the map in the format of a map file.
+impl fmt::Display for SymbolMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (addr, name) in &self.symbols {
+            writeln!(f, "{addr:08X} {name}")?;
+        }
+        Ok(())
+    }
+}
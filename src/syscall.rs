@@ -0,0 +1,164 @@
+//! Standard syscalls, serviced by the host when a program raises an interrupt with their code.
+//!
+//! | Code | Name        | Description                                                                                       |
+//! |:----:|:-----------:|:--------------------------------------------------------------------------------------------------|
+//! | `10` | `PRINT`     | Prints the null-terminated string at `PTR`.                                                       |
+//! | `11` | `READ_LINE` | Reads a line, without its newline, to `PTR` as a null-terminated string of at most `VAL2` bytes.<br/>Puts its length in `VAL1`, or `FFFFFFFF` at the end of input. |
+//! | `12` | `TIME`      | Puts the seconds since the Unix epoch in `VAL1`, as a u32.                                        |
+//! | `13` | `EXIT`      | Halts with the code in `VAL2`.                                                                    |
+//!
+//! Values in `VAL1` and `VAL2` are u32s. If the host fails to read or write,
+//! the syscall halts with the code [`IO_ERROR`].
+//!
+//! ```rust
+//! # use lasagna::console::SharedBuffer;
+//! # use lasagna::emulator::{Emulator, Interrupt};
+//! # use lasagna::parser::assemble;
+//! # use lasagna::syscall;
+//! let output = SharedBuffer::new();
+//! let mut emulator = syscall::standard(Emulator::default(), &b"world\n"[..], output.clone());
+//!
+//! let program = assemble("
+//!     right u32
+//!     literal 'Hello, '
+//!     left u32
+//!     literal 0x10_u32 [ PRINT ]
+//!     read u32
+//!     right u32
+//!     interrupt
+//!     left u32
+//!     literal 16_u32
+//!     read u32
+//!     swap
+//!     literal 0x11_u32 [ READ_LINE ]
+//!     read u32
+//!     right u32
+//!     interrupt
+//!     left u32
+//!     literal 0x10_u32 [ PRINT ]
+//!     read u32
+//!     right u32
+//!     interrupt
+//!     left u32
+//!     literal 3_u32
+//!     read u32
+//!     swap
+//!     literal 0x13_u32 [ EXIT ]
+//!     read u32
+//!     interrupt
+//! ").unwrap();
+//! emulator.memory[0x20000 .. 0x20000 + program.len()].copy_from_slice(&program);
+//! let interrupt = emulator.find_map(|result| result).unwrap();
+//! assert!(matches!(interrupt, Interrupt::User {code: 3, ..}));
+//! assert_eq!(output.contents(), b"Hello, world");
+//! ```
+use std::io::{Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::emulator::{Emulator, SyscallResult};
+
+pub use crate::console::IO_ERROR;
+
+/// Prints the null-terminated string at `PTR`.
+pub const PRINT: u32 = 0x10;
+/// Reads a line to `PTR`, as a null-terminated string of at most `VAL2` bytes.
+pub const READ_LINE: u32 = 0x11;
+/// Puts the seconds since the Unix epoch in `VAL1`.
+pub const TIME: u32 = 0x12;
+/// Halts with the code in `VAL2`.
+pub const EXIT: u32 = 0x13;
+
+/// Halts with the code [`IO_ERROR`].
+fn io_error(emulator: &mut Emulator) -> SyscallResult {
+    emulator.val1 = IO_ERROR.to_be_bytes();
+    SyscallResult::Halt
+}
+
+/// Creates a handler for [`PRINT`], printing to `output`.
+///
+/// A string without a null terminator is printed up to the end of memory.
+pub fn print(mut output: impl Write) -> impl FnMut(&mut Emulator) -> SyscallResult {
+    move |emulator| {
+        let start = (emulator.ptr as usize).min(emulator.memory.len());
+        let string = &emulator.memory[start..];
+        let end = string.iter().position(|b| *b == 0).unwrap_or(string.len());
+        match output.write_all(&string[..end]).and_then(|_| output.flush()) {
+            Ok(()) => SyscallResult::Resume,
+            Err(_) => io_error(emulator)
+        }
+    }
+}
+
+/// Creates a handler for [`READ_LINE`], reading from `input`.
+///
+/// Input is read a byte at a time, so nothing past the line is taken from `input`.
+/// Anything that doesn't fit is discarded, and so is a carriage return before the newline.
+/// The line is written like the `write` instruction would, so writing it out of bounds
+/// raises [`Interrupt::OutOfBounds`](crate::emulator::Interrupt::OutOfBounds).
+///
+/// ```rust
+/// # use lasagna::emulator::{Access, Emulator, Interrupt};
+/// # use lasagna::syscall::{self, READ_LINE};
+/// let mut emulator = Emulator::builder().ptr(0xFFFFC).val1(READ_LINE.to_be_bytes()).val2([0, 0, 0, 16]).build().unwrap()
+///     .with_syscall(READ_LINE, syscall::read_line(&b"overflowing\n"[..]));
+/// emulator.memory[0x20000] = 0b00_011_000; // interrupt
+/// assert!(matches!(emulator.step(), Some(Interrupt::OutOfBounds {access: Access::Write, ..})));
+/// assert_eq!(&emulator.memory[0xFFFFC..], &[0; 4]);
+/// ```
+pub fn read_line(mut input: impl Read) -> impl FnMut(&mut Emulator) -> SyscallResult {
+    move |emulator| {
+        let mut line = Vec::new();
+        let mut byte = [0];
+        let eof = loop {
+            match input.read(&mut byte) {
+                Ok(0) => break line.is_empty(),
+                Ok(_) if byte[0] == b'\n' => break false,
+                Ok(_) => line.push(byte[0]),
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {},
+                Err(_) => return io_error(emulator)
+            }
+        };
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        if eof {
+            emulator.val1 = u32::MAX.to_be_bytes();
+            return SyscallResult::Resume;
+        }
+
+        let room = u32::from_be_bytes(emulator.val2) as usize;
+        if room == 0 {
+            emulator.val1 = [0; 4];
+            return SyscallResult::Resume;
+        }
+        line.truncate(room - 1);
+        line.push(0);
+        if let Err(interrupt) = emulator.write_ptr(&line) {
+            return SyscallResult::Raise(interrupt);
+        }
+        emulator.val1 = ((line.len() - 1) as u32).to_be_bytes();
+        SyscallResult::Resume
+    }
+}
+
+/// The handler for [`TIME`].
+pub fn time(emulator: &mut Emulator) -> SyscallResult {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    emulator.val1 = (seconds as u32).to_be_bytes();
+    SyscallResult::Resume
+}
+
+/// The handler for [`EXIT`].
+pub fn exit(emulator: &mut Emulator) -> SyscallResult {
+    emulator.val1 = emulator.val2;
+    SyscallResult::Halt
+}
+
+/// Adds all of the standard syscalls to an emulator, reading from `input` and printing to `output`.
+pub fn standard(emulator: Emulator, input: impl Read + 'static, output: impl Write + 'static) -> Emulator {
+    emulator
+        .with_syscall(PRINT, print(output))
+        .with_syscall(READ_LINE, read_line(input))
+        .with_syscall(TIME, time)
+        .with_syscall(EXIT, exit)
+}
@@ -0,0 +1,410 @@
+//! Tracing what an emulator does on every step.
+//!
+//! [`Emulator::traced_step`] steps an emulator while recording the instruction it ran,
+//! its registers before and after, the memory it accessed, and any interrupt it raised.
+//! A [`TraceWriter`] writes these records out as text or in a binary format,
+//! and a [`TraceReader`] reads the binary format back.
+//!
+//! # Text Format
+//! Each step is a line, made of the address and instruction, the registers it changed,
+//! its memory accesses, and then its interrupt, separated by `|`.
+//! Values of `VAL1` and `VAL2` are given as their bytes.
+//!
+//! ```text
+//! [00020007] read u16 | VAL1=12340000 CUR=00020008 | R@00000000=1234
+//! ```
+//!
+//! # Binary Format
+//! Everything is big-endian. A trace starts with the magic bytes `LSGT`,
+//! the format version as a u16, currently `1`, and two reserved null bytes.
+//! Each step then follows, until the end of the file:
+//!
+//! | Size  | Description                                                           |
+//! |:-----:|:----------------------------------------------------------------------|
+//! | `4`   | The length of the instruction, as a u32.                              |
+//! | ...   | The bytes of the instruction.                                         |
+//! | `20`  | The registers before the step, as `VAL1`, `VAL2`, `PTR`, `CUR` and `STAT`. |
+//! | `20`  | The registers after the step.                                         |
+//! | `4`   | The number of memory accesses, as a u32.                              |
+//! | ...   | Each access, as `00` for reads or `01` for writes, its address and length as u32s, and its bytes. |
+//! | `1`   | The interrupt raised, as below, or `00` if there wasn't one.          |
+//! | `13`  | If there was an interrupt, its code or address, access, `CUR` and `PTR`. |
+//!
+//! Interrupts are `01` for halting, `02` for out of bounds, `03` for stack overflows,
+//! `04` for stack underflows, `05` for dividing by zero, `06` for user interrupts,
+//! `07` for debugger interrupts, and `08` for device interrupts.
+//! Out of bounds interrupts hold their address and then their access,
+//! as `00` for reads, `01` for writes, `02` for execution and `03` for moving the pointer.
+//! User, debugger and device interrupts hold their code, and then `00`.
+//! The rest hold four null bytes and then `00`.
+//!
+//! ```rust
+//! # use lasagna::emulator::{Emulator, Interrupt};
+//! # use lasagna::parser::assemble;
+//! # use lasagna::trace::{Format, TraceReader, TraceWriter};
+//! let program = assemble("literal 0x1234_u16\nread u16\ninterrupt").unwrap();
+//! let mut emulator = Emulator::default();
+//! emulator.memory[0x20000 .. 0x20000 + program.len()].copy_from_slice(&program);
+//!
+//! let mut writer = TraceWriter::new(Vec::new(), Format::Binary).unwrap();
+//! while writer.step(&mut emulator).unwrap().is_none() {}
+//!
+//! let trace = writer.into_inner();
+//! let records: Vec<_> = TraceReader::new(&trace[..]).unwrap().collect::<Result<_, _>>().unwrap();
+//! assert_eq!(records.len(), 3);
+//! assert_eq!(
+//!     records[1].to_string(),
+//!     "[00020007] read u16 | VAL1=12340000 CUR=00020008 | R@00000000=1234"
+//! );
+//! assert!(matches!(records[2].interrupt, Some(Interrupt::User {code: 0x12340000, ..})));
+//! ```
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::disassembler::DecodedInstruction;
+use crate::emulator::{Access, Emulator, Interrupt, StepResult};
+use crate::isa::Instruction;
+
+/// The magic bytes a binary trace starts with.
+pub const MAGIC: [u8; 4] = *b"LSGT";
+
+/// The version of the binary format that traces are written in.
+pub const VERSION: u16 = 1;
+
+/// The registers of an emulator at some point.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Registers {
+    pub val1: [u8; 4],
+    pub val2: [u8; 4],
+    pub ptr:  u32,
+    pub cur:  u32,
+    pub stat: u32
+}
+
+impl Registers {
+    /// Gets the registers of an emulator.
+    pub fn of(emulator: &Emulator) -> Self {
+        Self {
+            val1: emulator.val1,
+            val2: emulator.val2,
+            ptr: emulator.ptr,
+            cur: emulator.cur,
+            stat: emulator.stat
+        }
+    }
+}
+
+/// A read or write of memory made by a step.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MemoryAccess {
+    /// Either [`Access::Read`] or [`Access::Write`].
+    pub access: Access,
+    pub addr: u32,
+    /// The bytes that were read or written.
+    pub data: Vec<u8>
+}
+
+/// Everything that happened in a single step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// The bytes of the instruction that was run.
+    /// This is empty if `CUR` was out of bounds.
+    pub bytes: Vec<u8>,
+    pub before: Registers,
+    pub after: Registers,
+    /// The memory accessed at `PTR` or on the stack, in order.
+    pub accesses: Vec<MemoryAccess>,
+    pub interrupt: Option<Interrupt>
+}
+
+impl TraceRecord {
+    /// Decodes the instruction that was run, if it could be.
+    pub fn instruction(&self) -> Option<Instruction> {
+        Instruction::decode(&self.bytes).map(|(instruction, _)| instruction)
+    }
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decoded = DecodedInstruction {
+            address: self.before.cur,
+            bytes: self.bytes.clone(),
+            instruction: self.instruction()
+        };
+        write!(f, "[{:08X}] {decoded} |", self.before.cur)?;
+
+        let (before, after) = (self.before, self.after);
+        let registers = [
+            ("VAL1", u32::from_be_bytes(before.val1), u32::from_be_bytes(after.val1)),
+            ("VAL2", u32::from_be_bytes(before.val2), u32::from_be_bytes(after.val2)),
+            ("PTR", before.ptr, after.ptr),
+            ("CUR", before.cur, after.cur),
+            ("STAT", before.stat, after.stat)
+        ];
+        for (name, _, value) in registers.iter().filter(|(_, before, after)| before != after) {
+            write!(f, " {name}={value:08X}")?;
+        }
+
+        if !self.accesses.is_empty() {
+            f.write_str(" |")?;
+            for access in &self.accesses {
+                let kind = if access.access == Access::Write { 'W' } else { 'R' };
+                write!(f, " {kind}@{:08X}=", access.addr)?;
+                for b in &access.data {
+                    write!(f, "{b:02X}")?;
+                }
+            }
+        }
+        if let Some(interrupt) = &self.interrupt {
+            write!(f, " | {interrupt}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Emulator {
+    /// Steps the emulator, recording what happened.
+    pub fn traced_step(&mut self) -> (TraceRecord, StepResult) {
+        let before = Registers::of(self);
+        let cur = self.cur as usize;
+        let bytes = match self.memory.get(cur..) {
+            Some(rest) => match Instruction::decode(rest) {
+                Some((_, length)) => rest[..length].to_vec(),
+                // Just the opcode and the length of the truncated literal
+                None => rest[..rest.len().min(5)].to_vec()
+            },
+            None => Vec::new()
+        };
+
+        self.accesses = Some(Vec::new());
+        let interrupt = self.step();
+        let accesses = self.accesses.take().unwrap_or_default();
+        let record = TraceRecord {bytes, before, after: Registers::of(self), accesses, interrupt};
+        (record, interrupt)
+    }
+}
+
+/// The format a [`TraceWriter`] writes traces in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Format {
+    Binary,
+    Text
+}
+
+/// Writes traces of an emulator's steps. See the [module documentation](self) for the formats.
+#[derive(Debug)]
+pub struct TraceWriter<W: Write> {
+    output: W,
+    format: Format
+}
+
+impl<W: Write> TraceWriter<W> {
+    /// Creates a writer, writing the header of the binary format if it's being used.
+    pub fn new(mut output: W, format: Format) -> io::Result<Self> {
+        if format == Format::Binary {
+            output.write_all(&MAGIC)?;
+            output.write_all(&VERSION.to_be_bytes())?;
+            output.write_all(&[0; 2])?;
+        }
+        Ok(Self {output, format})
+    }
+
+    /// Steps the emulator, and writes what happened.
+    pub fn step(&mut self, emulator: &mut Emulator) -> io::Result<StepResult> {
+        let (record, result) = emulator.traced_step();
+        self.write(&record)?;
+        Ok(result)
+    }
+
+    /// Writes a record.
+    pub fn write(&mut self, record: &TraceRecord) -> io::Result<()> {
+        match self.format {
+            Format::Text => writeln!(self.output, "{record}"),
+            Format::Binary => self.output.write_all(&encode(record))
+        }
+    }
+
+    /// Flushes the output, and returns it.
+    pub fn into_inner(mut self) -> W {
+        let _ = self.output.flush();
+        self.output
+    }
+}
+
+fn encode_registers(output: &mut Vec<u8>, registers: &Registers) {
+    output.extend_from_slice(&registers.val1);
+    output.extend_from_slice(&registers.val2);
+    for value in [registers.ptr, registers.cur, registers.stat] {
+        output.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+fn encode(record: &TraceRecord) -> Vec<u8> {
+    let mut output = Vec::new();
+    output.extend_from_slice(&(record.bytes.len() as u32).to_be_bytes());
+    output.extend_from_slice(&record.bytes);
+    encode_registers(&mut output, &record.before);
+    encode_registers(&mut output, &record.after);
+
+    output.extend_from_slice(&(record.accesses.len() as u32).to_be_bytes());
+    for access in &record.accesses {
+        output.push((access.access == Access::Write) as u8);
+        output.extend_from_slice(&access.addr.to_be_bytes());
+        output.extend_from_slice(&(access.data.len() as u32).to_be_bytes());
+        output.extend_from_slice(&access.data);
+    }
+
+    let Some(interrupt) = record.interrupt else {
+        output.push(0);
+        return output;
+    };
+    let (kind, value, access) = match interrupt {
+        Interrupt::Halt {..} => (1, 0, 0),
+        Interrupt::OutOfBounds {addr, access, ..} => (2, addr, match access {
+            Access::Read => 0,
+            Access::Write => 1,
+            Access::Execute => 2,
+            Access::Pointer => 3
+        }),
+        Interrupt::StackOverflow {..} => (3, 0, 0),
+        Interrupt::StackUnderflow {..} => (4, 0, 0),
+        Interrupt::DivideByZero {..} => (5, 0, 0),
+        Interrupt::User {code, ..} => (6, code, 0),
+        Interrupt::Debugger {code, ..} => (7, code, 0),
+        Interrupt::Device {code, ..} => (8, code, 0)
+    };
+    output.push(kind);
+    output.extend_from_slice(&value.to_be_bytes());
+    output.push(access);
+    output.extend_from_slice(&interrupt.cur().to_be_bytes());
+    output.extend_from_slice(&interrupt.ptr().to_be_bytes());
+    output
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads traces in the binary format, iterating over their records.
+#[derive(Debug)]
+pub struct TraceReader<R: Read> {
+    input: R
+}
+
+impl<R: Read> TraceReader<R> {
+    /// Creates a reader, reading the header of the trace.
+    ///
+    /// # Errors
+    /// * The input doesn't start with a trace header, or it's for a different version.
+    /// * The input couldn't be read.
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut header = [0; 8];
+        input.read_exact(&mut header)?;
+        if header[..4] != MAGIC {
+            return Err(invalid("not a trace"));
+        }
+        if header[4..6] != VERSION.to_be_bytes() {
+            return Err(invalid("unsupported trace version"));
+        }
+        Ok(Self {input})
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut bytes = [0; N];
+        self.input.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    fn bytes(&mut self) -> io::Result<Vec<u8>> {
+        let length = self.u32()?;
+        self.bytes_of_length(length)
+    }
+
+    fn bytes_of_length(&mut self, length: u32) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        (&mut self.input).take(length as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != length as usize {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(bytes)
+    }
+
+    fn registers(&mut self) -> io::Result<Registers> {
+        Ok(Registers {
+            val1: self.array()?,
+            val2: self.array()?,
+            ptr: self.u32()?,
+            cur: self.u32()?,
+            stat: self.u32()?
+        })
+    }
+
+    fn record(&mut self, first: u8) -> io::Result<TraceRecord> {
+        let [a, b, c] = self.array()?;
+        let bytes = self.bytes_of_length(u32::from_be_bytes([first, a, b, c]))?;
+        let before = self.registers()?;
+        let after = self.registers()?;
+
+        let count = self.u32()?;
+        let mut accesses = Vec::new();
+        for _ in 0..count {
+            let access = match self.array::<1>()?[0] {
+                0 => Access::Read,
+                1 => Access::Write,
+                _ => return Err(invalid("invalid memory access"))
+            };
+            let addr = self.u32()?;
+            let data = self.bytes()?;
+            accesses.push(MemoryAccess {access, addr, data});
+        }
+
+        let kind = self.array::<1>()?[0];
+        let interrupt = if kind == 0 {
+            None
+        } else {
+            let value = self.u32()?;
+            let access = self.array::<1>()?[0];
+            let (cur, ptr) = (self.u32()?, self.u32()?);
+            Some(match kind {
+                1 => Interrupt::Halt {cur, ptr},
+                2 => Interrupt::OutOfBounds {addr: value, cur, ptr, access: match access {
+                    0 => Access::Read,
+                    1 => Access::Write,
+                    2 => Access::Execute,
+                    3 => Access::Pointer,
+                    _ => return Err(invalid("invalid memory access"))
+                }},
+                3 => Interrupt::StackOverflow {cur, ptr},
+                4 => Interrupt::StackUnderflow {cur, ptr},
+                5 => Interrupt::DivideByZero {cur, ptr},
+                6 => Interrupt::User {code: value, cur, ptr},
+                7 => Interrupt::Debugger {code: value, cur, ptr},
+                8 => Interrupt::Device {code: value, cur, ptr},
+                _ => return Err(invalid("invalid interrupt"))
+            })
+        };
+        Ok(TraceRecord {bytes, before, after, accesses, interrupt})
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = io::Result<TraceRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The end of the trace is only valid between records
+        let mut first = [0];
+        loop {
+            match self.input.read(&mut first) {
+                Ok(0) => return None,
+                Ok(_) => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+                Err(err) => return Some(Err(err))
+            }
+        }
+        Some(self.record(first[0]))
+    }
+}
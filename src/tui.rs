@@ -0,0 +1,445 @@
+//! A full-screen debugger for an emulator, like the one behind `lasagna tui`.
+//!
+//! The screen is split into panes, showing the disassembly around a cursor that follows `CUR`,
+//! the registers with `VAL1` and `VAL2` as every type, the memory around `PTR`,
+//! the stack, and anything the program printed.
+//!
+//! | Key          | Action                                                 |
+//! |:-------------|:-------------------------------------------------------|
+//! | `s`, space   | Steps one instruction.                                 |
+//! | `c`          | Runs until a breakpoint or an interrupt.               |
+//! | `r`          | Runs until the instruction under the cursor.           |
+//! | `b`          | Toggles a breakpoint on the instruction under the cursor. |
+//! | `k`, `j`, up, down | Moves the cursor.                                |
+//! | `g`          | Moves the cursor back to `CUR`.                        |
+//! | `q`          | Quits.                                                 |
+//!
+//! While running, pressing any key pauses the emulator.
+//!
+//! [`Tui::run`] takes over the terminal, drawing with ANSI escape codes
+//! and switching it into raw mode with `stty`, so it needs a Unix-like terminal.
+//! The rest can be driven without one:
+//!
+//! ```rust
+//! # use lasagna::emulator::Emulator;
+//! # use lasagna::parser::assemble_with_symbols;
+//! # use lasagna::tui::{Key, Tui};
+//! let (program, symbols) = assemble_with_symbols("
+//!     literal 0x1234_u16
+//!     label main
+//!     read u16
+//!     push
+//!     interrupt
+//! ").unwrap();
+//! let mut emulator = Emulator::default();
+//! emulator.memory[0x20000 .. 0x20000 + program.len()].copy_from_slice(&program);
+//! let mut tui = Tui::new(emulator, symbols);
+//!
+//! // Run to the `push`, and then step over it
+//! tui.press(Key::Down);
+//! tui.press(Key::Down);
+//! tui.press(Key::Char('r'));
+//! while tui.tick() {}
+//! assert_eq!(tui.emulator.cur, 0x20008);
+//! tui.press(Key::Char('s'));
+//!
+//! let screen = tui.render(80, 24);
+//! assert_eq!(screen.len(), 24);
+//! assert!(screen.iter().all(|line| line.chars().count() == 80));
+//! assert!(screen.iter().any(|line| line.contains(">  => [00020009] <main+2> interrupt")));
+//! assert!(screen.iter().any(|line| line.contains("u16   4660")));
+//! assert!(screen.iter().any(|line| line.contains("[0] 00010004  12 34 00 00")));
+//! ```
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use crate::console::SharedBuffer;
+use crate::constants;
+use crate::disassembler::{disassemble_around, DecodedInstruction};
+use crate::emulator::Emulator;
+use crate::isa::Type;
+use crate::repl::{describe, hex, interpret, symbol};
+use crate::symbols::SymbolMap;
+
+/// How many steps to run between checking for key presses.
+const STEPS_PER_TICK: u64 = 100_000;
+
+/// The width of the right column, holding the registers, memory and stack.
+const RIGHT_WIDTH: usize = 44;
+
+/// How many lines of output to show, if there's any.
+const OUTPUT_LINES: usize = 5;
+
+const HELP: &str = "s step  c continue  r run to cursor  b breakpoint  j/k move  g go to CUR  q quit";
+
+/// A key that was pressed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Key {
+    Up,
+    Down,
+    Char(char)
+}
+
+impl Key {
+    /// Parses the keys in what was read from a terminal, skipping anything unrecognised.
+    ///
+    /// ```rust
+    /// # use lasagna::tui::Key;
+    /// assert_eq!(Key::parse(b"s\x1b[Aq"), [Key::Char('s'), Key::Up, Key::Char('q')]);
+    /// ```
+    pub fn parse(bytes: &[u8]) -> Vec<Self> {
+        let mut keys = Vec::new();
+        let mut rest = bytes;
+        while let Some((&first, tail)) = rest.split_first() {
+            rest = tail;
+            match (first, rest) {
+                (0x1B, [b'[' | b'O', code, tail @ ..]) => {
+                    rest = tail;
+                    match code {
+                        b'A' => keys.push(Self::Up),
+                        b'B' => keys.push(Self::Down),
+                        _ => ()
+                    }
+                },
+                (byte, _) if byte.is_ascii_graphic() || byte == b' ' => keys.push(Self::Char(byte as char)),
+                _ => ()
+            }
+        }
+        keys
+    }
+}
+
+/// A full-screen debugger. See the [module documentation](self).
+pub struct Tui {
+    pub emulator: Emulator,
+    pub symbols: SymbolMap,
+    /// Where the program's output goes, to be shown in a pane.
+    pub output: Option<SharedBuffer>,
+    /// The address of the instruction under the cursor.
+    cursor: u32,
+    running: bool,
+    /// A breakpoint added to run to the cursor, to be removed once stopped.
+    temporary: Option<u32>,
+    /// What happened last, shown above the keys.
+    message: String
+}
+
+impl Tui {
+    /// Creates a debugger for an emulator, naming addresses with the given symbols.
+    pub fn new(emulator: Emulator, symbols: SymbolMap) -> Self {
+        let cursor = emulator.cur;
+        Self {emulator, symbols, output: None, cursor, running: false, temporary: None, message: String::new()}
+    }
+
+    /// Checks if the emulator is running.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Handles a key press. Returns `false` if it was to quit.
+    pub fn press(&mut self, key: Key) -> bool {
+        if self.running {
+            self.stop("paused".into());
+            return true;
+        }
+        match key {
+            Key::Char('q') => return false,
+            Key::Char('s' | ' ') => {
+                let message = match self.emulator.step_watched() {
+                    Some(reason) => reason.to_string(),
+                    None => String::new()
+                };
+                self.stop(message);
+            },
+            Key::Char('c') => self.start(None),
+            Key::Char('r') => self.start(Some(self.cursor)),
+            Key::Char('b') => self.toggle_breakpoint(),
+            Key::Char('k') | Key::Up => self.cursor = self.around(1, 0)[0].address,
+            Key::Char('j') | Key::Down => {
+                if let Some(next) = self.around(0, 1).get(1) {
+                    self.cursor = next.address;
+                }
+            },
+            Key::Char('g') => self.cursor = self.emulator.cur,
+            _ => ()
+        }
+        true
+    }
+
+    fn toggle_breakpoint(&mut self) {
+        if !self.emulator.add_breakpoint(self.cursor) {
+            self.emulator.remove_breakpoint(self.cursor);
+        }
+    }
+
+    /// Runs the emulator for a while if it's running. Returns whether it's still running.
+    pub fn tick(&mut self) -> bool {
+        if self.running {
+            if let Some(reason) = self.emulator.run_for(STEPS_PER_TICK) {
+                self.stop(reason.to_string());
+            }
+        }
+        self.running
+    }
+
+    /// Starts running, until a breakpoint at `to` if there is one.
+    /// The first instruction is always run, so that continuing from a breakpoint moves past it.
+    fn start(&mut self, to: Option<u32>) {
+        if let Some(to) = to {
+            if self.emulator.add_breakpoint(to) {
+                self.temporary = Some(to);
+            }
+        }
+        match self.emulator.step_watched() {
+            Some(reason) => self.stop(reason.to_string()),
+            None => {
+                self.running = true;
+                self.message = "running, press any key to pause".into();
+            }
+        }
+    }
+
+    fn stop(&mut self, message: String) {
+        if let Some(temporary) = self.temporary.take() {
+            self.emulator.remove_breakpoint(temporary);
+        }
+        self.running = false;
+        self.cursor = self.emulator.cur;
+        self.message = message;
+    }
+
+    /// The instructions around the cursor.
+    fn around(&self, before: usize, after: usize) -> Vec<DecodedInstruction> {
+        let instructions = disassemble_around(&self.emulator.memory, constants::PROGRAM_START, self.cursor, before, after);
+        if instructions.is_empty() {
+            vec![DecodedInstruction {address: self.cursor, bytes: Vec::new(), instruction: None}]
+        } else {
+            instructions
+        }
+    }
+
+    /// Draws the screen as lines of text, at least 80 by 24 characters.
+    pub fn render(&self, width: usize, height: usize) -> Vec<String> {
+        let (width, height) = (width.max(80), height.max(24));
+        let body = height - 3;
+        let left_width = width - RIGHT_WIDTH - 1;
+
+        let title = format!(" lasagna  CUR {}  {}", describe(&self.symbols, self.emulator.cur),
+            if self.running { "running" } else { "stopped" });
+        let mut lines = vec![fit(&title, width)];
+
+        let output = self.output_lines();
+        let code_height = if output.is_empty() { body } else { body - output.len() - 1 };
+        let mut left = self.code(code_height);
+        if !output.is_empty() {
+            left.push("Output".into());
+            left.extend(output);
+        }
+        let right = self.registers_and_memory(body);
+        for i in 0..body {
+            let left = left.get(i).map_or("", String::as_str);
+            let right = right.get(i).map_or("", String::as_str);
+            lines.push(format!("{}|{}", fit(left, left_width), fit(right, RIGHT_WIDTH)));
+        }
+        lines.push(fit(&self.message, width));
+        lines.push(fit(HELP, width));
+        lines
+    }
+
+    /// The disassembly pane, with the cursor a third of the way down.
+    fn code(&self, height: usize) -> Vec<String> {
+        let mut lines = vec!["Disassembly".to_string()];
+        let before = (height - 1) / 3;
+        for instruction in self.around(before, height) {
+            if lines.len() == height {
+                break;
+            }
+            let addr = instruction.address;
+            if let Some(name) = self.symbols.name(addr) {
+                lines.push(format!("{name}:"));
+                if lines.len() == height {
+                    break;
+                }
+            }
+            let cursor = if addr == self.cursor { '>' } else { ' ' };
+            let breakpoint = if self.emulator.breakpoints.addresses().any(|other| other == addr) { '*' } else { ' ' };
+            let cur = if addr == self.emulator.cur { "=>" } else { "  " };
+            let symbol = symbol(&self.symbols, addr).map_or_else(String::new, |symbol| symbol + " ");
+            lines.push(format!("{cursor}{breakpoint} {cur} [{addr:08X}] {symbol}{instruction}"));
+        }
+        lines
+    }
+
+    /// The registers, memory and stack panes.
+    fn registers_and_memory(&self, height: usize) -> Vec<String> {
+        let emulator = &self.emulator;
+        let [val1, val2] = [emulator.val1, emulator.val2];
+        let mut lines = vec![
+            format!("{:<6}{:<19}{}", "", "VAL1", "VAL2"),
+            format!("{:<6}{:<19}{}", "bytes", hex(&val1), hex(&val2))
+        ];
+        for ty in Type::ALL {
+            lines.push(format!("{:<6}{:<19}{}", ty.name(), interpret(val1, ty), interpret(val2, ty)));
+        }
+        lines.push(format!("PTR   {}", describe(&self.symbols, emulator.ptr)));
+        lines.push(format!("STAT  {:08X}", emulator.stat));
+
+        // What's left after the headers is split between memory and the stack
+        let memory_rows = height.saturating_sub(lines.len() + 4) / 2;
+        lines.push(String::new());
+        lines.push("Memory at PTR".into());
+        let ptr = emulator.ptr as usize;
+        let start = (ptr & !7).saturating_sub(memory_rows / 2 * 8);
+        for row in 0..memory_rows {
+            let addr = start + row * 8;
+            let Some(bytes) = emulator.memory.get(addr .. addr + 8) else {
+                break;
+            };
+            // The byte at `PTR` is put in brackets
+            let mut separators = [' '; 9];
+            if (addr .. addr + 8).contains(&ptr) {
+                separators[ptr - addr] = '[';
+                separators[ptr - addr + 1] = ']';
+            }
+            let mut line = format!("{addr:08X} ");
+            for (byte, separator) in bytes.iter().zip(separators) {
+                line.push(separator);
+                line += &format!("{byte:02X}");
+            }
+            line.push(separators[8]);
+            line.push(' ');
+            line.extend(bytes.iter().map(|b| if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' }));
+            lines.push(line);
+        }
+
+        lines.push(String::new());
+        let length = u16::from_be_bytes([emulator.memory[0x10002], emulator.memory[0x10003]]) as usize;
+        lines.push(format!("Stack (length {length} at 00010002)"));
+        for i in (0..length).rev() {
+            if lines.len() >= height {
+                break;
+            }
+            let addr = 0x10004 + i * 4;
+            let Some(value) = emulator.memory.get(addr .. addr + 4) else {
+                continue;
+            };
+            lines.push(format!("[{i}] {addr:08X}  {}", hex(value)));
+        }
+        lines
+    }
+
+    /// The last few lines the program printed.
+    fn output_lines(&self) -> Vec<String> {
+        let Some(output) = &self.output else {
+            return Vec::new();
+        };
+        let contents = output.contents();
+        let text = String::from_utf8_lossy(&contents);
+        let lines: Vec<&str> = text.lines().collect();
+        lines[lines.len().saturating_sub(OUTPUT_LINES)..].iter()
+            .map(|line| line.chars().filter(|c| !c.is_control()).collect())
+            .collect()
+    }
+
+    /// Takes over the terminal until `q` is pressed.
+    ///
+    /// # Errors
+    /// The terminal couldn't be switched into raw mode, or reading from or writing to it failed.
+    pub fn run(&mut self) -> io::Result<()> {
+        let terminal = RawMode::enable()?;
+        let mut stdout = io::stdout();
+        // Switch to the alternate screen, and hide the cursor
+        stdout.write_all(b"\x1b[?1049h\x1b[?25l")?;
+        let result = self.event_loop(&mut stdout);
+        stdout.write_all(b"\x1b[?25h\x1b[?1049l")?;
+        stdout.flush()?;
+        drop(terminal);
+        result
+    }
+
+    fn event_loop(&mut self, stdout: &mut io::Stdout) -> io::Result<()> {
+        let mut buffer = [0; 64];
+        let mut size = (0, 0);
+        let mut last = String::new();
+        for iteration in 0u64.. {
+            // Finding the size means running `stty`, so it's only checked every so often
+            if iteration % 25 == 0 {
+                let new = terminal_size();
+                if new != size {
+                    size = new;
+                    last.clear();
+                    stdout.write_all(b"\x1b[2J")?;
+                }
+            }
+            let mut frame = String::from("\x1b[H");
+            for (i, line) in self.render(size.0, size.1).iter().enumerate() {
+                // The title is highlighted
+                match i {
+                    0 => frame += &format!("\x1b[7m{line}\x1b[0m"),
+                    _ => frame += &format!("\r\n{line}")
+                }
+            }
+            if frame != last {
+                stdout.write_all(frame.as_bytes())?;
+                stdout.flush()?;
+                last = frame;
+            }
+
+            let running = self.tick();
+            let count = io::stdin().read(&mut buffer)?;
+            if count == 0 && !running {
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            for key in Key::parse(&buffer[..count]) {
+                if !self.press(key) {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Puts the terminal into raw mode with `stty`, restoring its settings when dropped.
+struct RawMode(String);
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        let saved = stty(&["-g"])?;
+        // Reads don't wait for input, so the emulator can keep running
+        stty(&["raw", "-echo", "min", "0", "time", "0"])?;
+        Ok(Self(saved.trim().to_string()))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.0]);
+    }
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty").args(args).stdin(Stdio::inherit()).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The width and height of the terminal, or 80 by 24 if it can't be found.
+fn terminal_size() -> (usize, usize) {
+    let size = stty(&["size"]).ok().and_then(|size| {
+        let (rows, columns) = size.trim().split_once(' ')?;
+        Some((columns.parse().ok()?, rows.parse().ok()?))
+    });
+    size.unwrap_or((80, 24))
+}
+
+/// Pads or cuts text to exactly `width` characters.
+fn fit(text: &str, width: usize) -> String {
+    let mut text: String = text.chars().take(width).collect();
+    let length = text.chars().count();
+    text.extend(std::iter::repeat_n(' ', width - length));
+    text
+}